@@ -0,0 +1,160 @@
+use anyhow::{Result, Context};
+use chrono::DateTime;
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+use url::form_urlencoded;
+
+use crate::{clients::client::{ESSClient, ResultFormatting, TableFormatting, ListFormatting, headers, list_rows}, dataplane::DataPlaneTarget};
+
+/// A newly created Elasticsearch API key
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiKeyCreated {
+    pub id: String,
+    pub name: String,
+    /// expiry time in milliseconds since the epoch
+    pub expiration: Option<i64>,
+    pub api_key: String,
+    /// the id and key, base64 encoded, as used in an `Authorization: ApiKey` header
+    pub encoded: String
+}
+
+impl ResultFormatting for ApiKeyCreated {
+    fn compact(&self) -> String {
+        format!("{}, {}", self.id, self.encoded)
+    }
+}
+
+impl ListFormatting for ApiKeyCreated {}
+
+impl TableFormatting for ApiKeyCreated {
+    fn headers(&self) -> Vec<String> {
+        headers(&["ID", "NAME", "EXPIRES", "ENCODED"])
+    }
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![self.id.clone(), self.name.clone(), format_millis(self.expiration), self.encoded.clone()]]
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiKeyList {
+    pub api_keys: Vec<ApiKeyInfo>
+}
+
+/// An existing Elasticsearch API key
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiKeyInfo {
+    pub id: String,
+    pub name: Option<String>,
+    /// creation time in milliseconds since the epoch
+    pub creation: Option<i64>,
+    /// expiry time in milliseconds since the epoch
+    pub expiration: Option<i64>,
+    #[serde(default)]
+    pub invalidated: bool,
+    pub username: Option<String>
+}
+
+impl ResultFormatting for ApiKeyList {
+    fn compact(&self) -> String {
+        let mut acc = String::new();
+        for key in &self.api_keys {
+            acc = format!("{}{}, {}\n", acc, key.name.as_deref().unwrap_or(""), key.id);
+        }
+        acc
+    }
+}
+
+impl ListFormatting for ApiKeyList {
+    fn list_rows(&self) -> Result<Vec<Value>> {
+        list_rows(&self.api_keys)
+    }
+}
+
+impl TableFormatting for ApiKeyList {
+    fn headers(&self) -> Vec<String> {
+        headers(&["ID", "NAME", "USER", "CREATED", "EXPIRES", "INVALIDATED"])
+    }
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.api_keys.iter().map(|k| vec![
+            k.id.clone(),
+            k.name.clone().unwrap_or_default(),
+            k.username.clone().unwrap_or_default(),
+            format_millis(k.creation),
+            format_millis(k.expiration),
+            k.invalidated.to_string()
+        ]).collect()
+    }
+}
+
+/// The result of invalidating API keys
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiKeyInvalidation {
+    #[serde(default)]
+    pub invalidated_api_keys: Vec<String>,
+    #[serde(default)]
+    pub previously_invalidated_api_keys: Vec<String>,
+    #[serde(default)]
+    pub error_count: u64
+}
+
+impl ResultFormatting for ApiKeyInvalidation {
+    fn compact(&self) -> String {
+        format!("invalidated: {}, already invalidated: {}, errors: {}",
+            self.invalidated_api_keys.join(","), self.previously_invalidated_api_keys.join(","), self.error_count)
+    }
+}
+
+impl ListFormatting for ApiKeyInvalidation {}
+
+impl TableFormatting for ApiKeyInvalidation {
+    fn headers(&self) -> Vec<String> {
+        headers(&["INVALIDATED", "ALREADY INVALIDATED", "ERRORS"])
+    }
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![self.invalidated_api_keys.join(","), self.previously_invalidated_api_keys.join(","), self.error_count.to_string()]]
+    }
+}
+
+/// format a timestamp in milliseconds since the epoch as RFC 3339
+fn format_millis(millis: Option<i64>) -> String {
+    millis.and_then(DateTime::from_timestamp_millis).map(|t| t.to_rfc3339()).unwrap_or_default()
+}
+
+/// create an API key. Role descriptors limit the key to a subset of the creating user's privileges.
+pub fn create(client: &ESSClient, es: &DataPlaneTarget, name: &str, role_descriptors: Option<Value>, expiration: Option<&str>) -> Result<ApiKeyCreated> {
+    let mut body = Map::new();
+    body.insert("name".to_string(), json!(name));
+    if let Some(roles) = role_descriptors {
+        body.insert("role_descriptors".to_string(), roles);
+    }
+    if let Some(exp) = expiration {
+        body.insert("expiration".to_string(), json!(exp));
+    }
+    es.call(client, Method::POST, "_security/api_key", Some(Value::Object(body))).context("error creating API key")
+}
+
+/// list the API keys owned by the user, optionally only those with a given name
+pub fn list(client: &ESSClient, es: &DataPlaneTarget, name: Option<&str>) -> Result<ApiKeyList> {
+    let mut path = "_security/api_key?owner=true".to_string();
+    if let Some(name) = name {
+        path = format!("{}&name={}", path, form_urlencoded::byte_serialize(name.as_bytes()).collect::<String>());
+    }
+    es.call(client, Method::GET, &path, None).context("error listing API keys")
+}
+
+/// invalidate API keys by ID
+pub fn invalidate(client: &ESSClient, es: &DataPlaneTarget, ids: &[String]) -> Result<ApiKeyInvalidation> {
+    es.call(client, Method::DELETE, "_security/api_key", Some(json!({"ids": ids}))).context("error invalidating API keys")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_millis;
+
+    #[test]
+    fn test_format_millis() {
+        assert_eq!(format_millis(Some(1692612672000)), "2023-08-21T10:11:12+00:00");
+        assert_eq!(format_millis(None), "");
+    }
+}
@@ -1,24 +1,199 @@
-use clap::{Parser, Subcommand, ValueEnum};
+use std::io::{BufRead, Read};
+
+use anyhow::{Result, Context, anyhow};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use reqwest::{Method, header::{HeaderMap, HeaderName, HeaderValue}};
+
+use crate::{clients::client::RequestParts, filter::{Predicate, parse_predicate}, query::{self, Query}, template::Template};
 
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
-    /// enable debug-level logging
+    /// enable debug-level logging, same as --log-level debug
     #[clap(long, short, default_value_t=false)]
     pub verbose: bool,
-    /// the format to print the output in
-    #[clap(value_enum, long, short, default_value_t=OutputType::Struct)]
-    pub out: OutputType,
+    /// print the method, URL and body of requests that would change anything, instead of sending them
+    #[clap(long, global = true, default_value_t=false)]
+    pub dry_run: bool,
+    /// print an equivalent curl command for requests that would change anything, instead of sending them.
+    /// The API key is read from the ESSCLI_API_KEY environment variable, and Elasticsearch and Kibana
+    /// credentials from ESSCLI_USERNAME and ESSCLI_PASSWORD
+    #[clap(long, global = true, default_value_t=false)]
+    pub print_curl: bool,
+    #[command(flatten)]
+    pub log: LogArgs,
+    #[command(flatten)]
+    pub output: OutputArgs,
     #[command(subcommand)]
     pub command: Types,
 }
 
+/// Options that control how results are printed
+#[derive(Args)]
+pub struct OutputArgs {
+    /// the format to print the output in
+    #[clap(value_enum, long, short, global = true, default_value_t=OutputType::Struct)]
+    pub out: OutputType,
+    /// don't print column headers in table, csv and tsv output
+    #[clap(long, global = true, default_value_t=false)]
+    pub no_headers: bool,
+    /// a jq-style expression to select part of the output, e.g. '.items[].id' or '.endpoints.elasticsearch'.
+    /// Results are printed as JSON.
+    #[clap(long, global = true, value_parser = query::parse, conflicts_with = "format")]
+    pub query: Option<Query>,
+    /// print string results of --query without quotes
+    #[clap(long, global = true, default_value_t=false)]
+    pub raw_output: bool,
+    /// print each item with a template, e.g. '{{.id}}\t{{.name}}', or the name of a format defined in the config file.
+    /// Values can be passed through helpers: '{{.metadata.created_at | date "%Y-%m-%d"}}', '{{.name | pad 20}}', '{{.alias | default "-"}}'
+    #[clap(long, global = true)]
+    pub format: Option<String>,
+    /// the parsed --format template, set once the config file is loaded
+    #[clap(skip)]
+    pub template: Option<Template>,
+    /// only print the IDs of the returned items
+    #[clap(long, short, global = true, default_value_t=false)]
+    pub quiet: bool,
+    /// print passwords, tokens and API keys instead of masking them in struct, compact and table output and in logs
+    #[clap(long, global = true, default_value_t=false)]
+    pub show_secrets: bool
+}
+
+/// One or more resource IDs, given as arguments or read from stdin
+#[derive(Args)]
+pub struct IdArgs {
+    /// IDs to act on. Use '-' to read newline-separated IDs from stdin.
+    #[clap(required_unless_present = "stdin")]
+    pub ids: Vec<String>,
+    /// Read newline-separated IDs from stdin, in addition to any given as arguments
+    #[clap(long, default_value_t=false)]
+    pub stdin: bool
+}
+
+impl IdArgs {
+    /// return the full list of IDs, reading from stdin if requested
+    pub fn resolve(&self) -> Result<Vec<String>> {
+        let mut ids: Vec<String> = self.ids.iter().filter(|id| *id != "-").cloned().collect();
+        if self.stdin || self.ids.iter().any(|id| id == "-") {
+            ids.extend(read_ids(std::io::stdin().lock())?);
+        }
+        Ok(ids)
+    }
+}
+
+/// read newline-separated IDs, ignoring blank lines and surrounding whitespace
+fn read_ids<R: BufRead>(reader: R) -> Result<Vec<String>> {
+    let mut ids = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let id = line.trim();
+        if !id.is_empty() {
+            ids.push(id.to_string());
+        }
+    }
+    Ok(ids)
+}
+
+/// Options that control logging
+#[derive(Args)]
+pub struct LogArgs {
+    /// the most verbose level to log at. trace also logs every HTTP request and response.
+    /// Defaults to info, or debug with --verbose
+    #[clap(value_enum, long, global = true)]
+    pub log_level: Option<LogLevel>,
+    /// append log lines to a file instead of writing them to stderr
+    #[clap(long, global = true)]
+    pub log_file: Option<String>,
+    /// the format to write log lines in
+    #[clap(value_enum, long, global = true, default_value_t=LogFormat::Text)]
+    pub log_format: LogFormat
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error
+}
+
+impl LogLevel {
+    pub fn filter(&self) -> log::LevelFilter {
+        match self {
+            LogLevel::Trace => log::LevelFilter::Trace,
+            LogLevel::Debug => log::LevelFilter::Debug,
+            LogLevel::Info => log::LevelFilter::Info,
+            LogLevel::Warn => log::LevelFilter::Warn,
+            LogLevel::Error => log::LevelFilter::Error
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum LogFormat {
+    Text,
+    Json
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 pub enum OutputType {
     Compact,
     Struct,
-    Json
+    Json,
+    Table,
+    Yaml,
+    Csv,
+    Tsv,
+    Ndjson
+}
+
+/// Options for filtering and shaping list output
+#[derive(Args)]
+pub struct ListArgs {
+    /// Only show items matching a filter: field=value, field~=regex, or created_after=<timestamp, date or duration>.
+    /// Can be repeated, in which case items must match every filter.
+    #[clap(long, value_parser = parse_predicate)]
+    pub filter: Vec<Predicate>,
+    /// Sort by the given field
+    #[clap(value_enum, long)]
+    pub sort: Option<SortField>,
+    /// Only show the first N items
+    #[clap(long)]
+    pub limit: Option<usize>,
+    /// Only show the given comma-separated fields, e.g. id,name,region
+    #[clap(long, value_delimiter = ',')]
+    pub columns: Option<Vec<String>>,
+    #[command(flatten)]
+    pub watch: WatchArgs
+}
+
+/// Options for re-polling a command and redrawing its output
+#[derive(Args)]
+pub struct WatchArgs {
+    /// Re-poll every interval (default 5s) and redraw the output in place, highlighting what changed
+    #[clap(long, num_args = 0..=1, require_equals = true, default_missing_value = "5s")]
+    pub watch: Option<humantime::Duration>
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum SortField {
+    #[value(name = "created_at")]
+    CreatedAt,
+    Name,
+    Region
+}
+
+impl SortField {
+    /// the item field this sort key refers to
+    pub fn field(&self) -> &'static str {
+        match self {
+            SortField::CreatedAt => "created_at",
+            SortField::Name => "name",
+            SortField::Region => "region"
+        }
+    }
 }
 
 #[derive(Subcommand)]
@@ -33,24 +208,164 @@ pub enum Types{
     SL(ServerlessCmd),
     /// Run a command against a traditional stateful deployment or configuration
     #[command(subcommand)]
-    SF(StatefulCmd)
+    SF(StatefulCmd),
+    /// List the projects and deployments created from this machine, along with their current status
+    Mine,
+    /// Delete or shut down every project and deployment created from this machine
+    Cleanup,
+    /// Poll serverless projects and stateful deployments, printing a JSON line for every change.
+    /// Watches both kinds of resources unless one is selected.
+    Events{
+        /// Watch serverless projects
+        #[clap(long, default_value_t=false)]
+        serverless: bool,
+        /// Watch stateful deployments
+        #[clap(long, default_value_t=false)]
+        stateful: bool,
+        /// How often to poll
+        #[clap(long, default_value = "10s")]
+        interval: humantime::Duration
+    },
+    /// Send a request to the Elasticsearch API of a serverless project or stateful deployment,
+    /// e.g. `es <id> GET _cluster/health`. Uses stored credentials, or resets them if there are none
+    Es{
+        /// the ID of the project or deployment
+        id: String,
+        #[command(flatten)]
+        req: RawReq
+    },
+    /// Send a request to the Kibana API of a serverless project or stateful deployment,
+    /// e.g. `kibana <id> GET api/status`. Sets the kbn-xsrf header, and uses credentials like `es`
+    Kibana{
+        /// the ID of the project or deployment
+        id: String,
+        /// the Kibana space to send the request to
+        #[clap(long)]
+        space: Option<String>,
+        #[command(flatten)]
+        req: RawReq
+    },
+    /// Run a raw request saved in the [recipes] section of the config file
+    Run{
+        /// the name of the recipe
+        recipe: String,
+        /// a value for a placeholder in the recipe, in the form name=value. Can be repeated
+        #[clap(long = "arg", short, value_parser = parse_param)]
+        args: Vec<(String, String)>,
+        /// print the response status and headers before the body
+        #[clap(long, short, default_value_t=false)]
+        include: bool
+    },
+    /// Delete serverless projects and shut down deployments that have outlived the TTL they were created with
+    Reap,
+    /// Load fixtures into a project or deployment: ingest pipelines, index templates, documents and Kibana saved objects
+    Seed{
+        /// the ID of the project or deployment
+        id: String,
+        /// the directory holding the fixtures
+        #[clap(long)]
+        dir: std::path::PathBuf,
+        /// how many documents to send in each bulk request
+        #[clap(long, default_value_t = 1000)]
+        batch_size: usize
+    },
+    /// Create and update projects and deployments to match a YAML or TOML manifest
+    Apply{
+        /// the manifest file. Files ending in .toml are read as TOML, anything else as YAML
+        #[clap(long, short, required_unless_present = "plan", conflicts_with = "plan")]
+        file: Option<std::path::PathBuf>,
+        /// also delete resources created from the manifest's label that it no longer lists
+        #[clap(long, default_value_t=false, conflicts_with = "plan")]
+        prune: bool,
+        /// carry out a plan written by `plan -o json` instead of comparing a manifest to the live resources again
+        #[clap(long)]
+        plan: Option<std::path::PathBuf>
+    },
+    /// Show what `apply` would change: a diff of each resource against a manifest, marking changes that cause
+    /// a rolling restart. Use `-o json` to save a plan for `apply --plan`
+    Plan{
+        /// the manifest file. Files ending in .toml are read as TOML, anything else as YAML
+        #[clap(long, short)]
+        file: std::path::PathBuf,
+        /// also plan to delete resources created from the manifest's label that it no longer lists
+        #[clap(long, default_value_t=false)]
+        prune: bool
+    },
+    /// Manage a warm pool of serverless projects, so CI jobs can check one out instead of waiting for a new project
+    Pool{
+        /// the pool file, which can be on a shared filesystem. Defaults to pool.json in the esscli config directory
+        #[clap(long, global = true)]
+        pool_file: Option<std::path::PathBuf>,
+        #[command(subcommand)]
+        cmd: PoolCmd
+    }
+}
+
+#[derive(Subcommand)]
+pub enum PoolCmd {
+    /// Create projects until the pool has the given number available, replacing projects whose lease expired
+    Fill{
+        /// how many available projects the pool should have
+        #[clap(long, default_value_t = 5)]
+        size: usize,
+        /// the project type. Defaults to the project type in the config file
+        #[clap(long = "type")]
+        project_type: Option<String>,
+        /// the region. Defaults to the serverless region in the config file
+        #[clap(long)]
+        region: Option<String>
+    },
+    /// Claim an available project and print its endpoints and credentials
+    Checkout{
+        /// the project type. Defaults to the project type in the config file
+        #[clap(long = "type")]
+        project_type: Option<String>,
+        /// only check out a project in this region
+        #[clap(long)]
+        region: Option<String>,
+        /// how long the project is held before the lease expires and `pool fill` replaces it, at most 30 days
+        #[clap(long, default_value = "1h", value_parser = parse_lease)]
+        lease: humantime::Duration,
+        /// who is holding the project, shown by `pool status`. Defaults to the current user
+        #[clap(long)]
+        holder: Option<String>,
+        /// print the project ID, endpoints and credentials as shell export lines, for use with eval
+        #[clap(long, default_value_t=false)]
+        export: bool
+    },
+    /// Return a checked out project to the pool
+    Checkin{
+        /// the ID of the project
+        id: String,
+        /// delete the project instead of returning it, so the next `pool fill` replaces it with a clean one
+        #[clap(long, default_value_t=false)]
+        destroy: bool
+    },
+    /// List the pooled projects and their leases
+    Status
 }
 
 #[derive(Subcommand)]
 pub enum ServerlessCmd{
     /// List all deployments
-    List,
-    /// Get a deployment by ID
-    Get{id: String},
+    List(ListArgs),
+    /// Get deployments by ID
+    Get(IdArgs),
     /// Reset the serverless credentials for a serverless instance
     ResetCreds{id: String},
+    /// Print the credentials saved in the local credential store for a project
+    Creds{id: String},
     /// Get the status of a deployment
-    Status{id: String},
+    Status{
+        #[command(flatten)]
+        ids: IdArgs,
+        #[command(flatten)]
+        watch: WatchArgs
+    },
     /// Send a raw request to ESS
-    #[command(subcommand)]
-    Raw(RawReq),    
-    /// Delete a deployment
-    Delete{ids: Vec<String>},
+    Raw(RawReq),
+    /// Delete deployments
+    Delete(IdArgs),
     /// Create a deployment
     Create{
         /// Name of the project
@@ -72,23 +387,96 @@ pub enum ServerlessCmd{
         kibana_docker_override: Option<String>,
         /// Override fleet image. Only usable internally.
         #[clap(long)]
-        fleet_docker_override: Option<String>
+        fleet_docker_override: Option<String>,
+        /// Delete the project with `esscli reap` once it is older than this, e.g. 4h or 1d
+        #[clap(long)]
+        ttl: Option<humantime::Duration>,
+        /// After creating, wait until Elasticsearch and Kibana answer requests. Implies --wait
+        #[clap(long, default_value_t=false)]
+        wait_ready: bool,
+        /// With --wait-ready, also wait for the Fleet server to report it's healthy
+        #[clap(long, default_value_t=false, requires = "wait_ready")]
+        wait_fleet: bool,
+        /// How long to wait for each component with --wait-ready
+        #[clap(long, default_value = "10m", requires = "wait_ready")]
+        ready_timeout: humantime::Duration
 
     },
     /// List all available regions
-    Regions
+    Regions,
+    /// Manage Elasticsearch API keys for a project
+    #[command(subcommand)]
+    Apikey(ApiKeyCmd),
+    /// Wait for Fleet in a project's Kibana, find or create an agent policy, and print the Fleet server URL
+    /// and an enrollment token for it
+    FleetBootstrap{
+        /// the ID of the project
+        id: String,
+        /// the name of the agent policy to enroll agents in. Created if it doesn't exist
+        #[clap(long, default_value_t=String::from("esscli"))]
+        policy_name: String,
+        /// print FLEET_URL and FLEET_ENROLLMENT_TOKEN as shell export lines, for use with eval
+        #[clap(long, default_value_t=false)]
+        export: bool,
+        /// how long to wait for Fleet to be ready
+        #[clap(long, default_value = "10m")]
+        timeout: humantime::Duration,
+        /// fail unless the project was created with this Fleet docker image override (see `create --fleet-docker-override`)
+        #[clap(long)]
+        fleet_image: Option<String>
+    }
+}
+
+#[derive(Subcommand)]
+pub enum ApiKeyCmd {
+    /// Create an API key, optionally limited to a set of role descriptors and expiring after a given time
+    Create{
+        /// the ID of the project
+        id: String,
+        /// the name of the key
+        #[clap(long)]
+        name: String,
+        /// role descriptors as a JSON object, or @file to read them from a file
+        #[clap(long)]
+        role_descriptors: Option<String>,
+        /// how long until the key expires, in Elasticsearch time units, e.g. 1d or 12h
+        #[clap(long)]
+        expiration: Option<String>
+    },
+    /// List the API keys created by the stored user
+    List{
+        /// the ID of the project
+        id: String,
+        /// only list keys with this name
+        #[clap(long)]
+        name: Option<String>
+    },
+    /// Invalidate API keys
+    Invalidate{
+        /// the ID of the project
+        id: String,
+        /// the IDs of the keys to invalidate
+        #[clap(required = true)]
+        key_ids: Vec<String>
+    }
 }
 
 #[derive(Subcommand)]
 pub enum StatefulCmd {
     /// list all deployments
-    List,
-    /// Get a deployment by ID
-    Get{id: String},
-    /// Shutdown a deployment
-    Shutdown{ids: Vec<String>},
-    /// Send a raw GET/POST request to ESS
-    #[command(subcommand)]
+    List(ListArgs),
+    /// Get deployments by ID
+    Get{
+        #[command(flatten)]
+        ids: IdArgs,
+        #[command(flatten)]
+        watch: WatchArgs
+    },
+    /// Shutdown deployments
+    Shutdown(IdArgs),
+    /// Print the credentials saved in the local credential store for a deployment
+    Creds{id: String},
+    /// Send a raw request to ESS
     Raw(RawReq),
     /// Create a new cluster from esscli's template, overriding any values written into the template.
     Create{
@@ -97,14 +485,128 @@ pub enum StatefulCmd {
         #[clap(long, short)]
         region: Option<String>,
         #[clap(long, short)]
-        version: Option<String>
+        version: Option<String>,
+        /// Shut down the deployment with `esscli reap` once it is older than this, e.g. 4h or 1d
+        #[clap(long)]
+        ttl: Option<humantime::Duration>
     }
 }
 
-#[derive(Subcommand)]
-pub enum RawReq {
-    /// Send a raw GET request
-    Get{path: String},
-    /// Send a raw POST request
-    Post{path: String, body: Option<String>}
-}
\ No newline at end of file
+/// A raw request, e.g. `raw DELETE projects/elasticsearch/<id>`
+#[derive(Args)]
+pub struct RawReq {
+    /// the HTTP method: GET, POST, PUT, PATCH, DELETE or HEAD
+    #[clap(value_parser = parse_method)]
+    pub method: Method,
+    /// the path, relative to the API endpoint
+    pub path: String,
+    /// the request body, same as --data
+    #[clap(conflicts_with = "data")]
+    pub body: Option<String>,
+    /// the request body. Use @file to read it from a file, or @- to read it from stdin
+    #[clap(long, short)]
+    pub data: Option<String>,
+    /// an extra request header, e.g. 'Content-Type: application/x-ndjson'. Can be repeated
+    #[clap(long = "header", short = 'H', value_parser = parse_header)]
+    pub headers: Vec<(String, String)>,
+    /// a URL query parameter in the form key=value. Can be repeated. This is --param rather than --query,
+    /// since the global --query option selects part of the output
+    #[clap(long = "param", short = 'p', value_parser = parse_param)]
+    pub params: Vec<(String, String)>,
+    /// print the response status and headers before the body
+    #[clap(long, short, default_value_t=false)]
+    pub include: bool
+}
+
+impl RawReq {
+    /// build the request, reading the body from a file or stdin if requested
+    pub fn parts(&self) -> Result<RequestParts> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            headers.insert(HeaderName::from_bytes(name.as_bytes())?, HeaderValue::from_str(value)?);
+        }
+        Ok(RequestParts {
+            method: self.method.clone(),
+            path: self.path.clone(),
+            params: self.params.clone(),
+            headers,
+            body: self.body()?
+        })
+    }
+
+    /// return the request body, reading it from a file or stdin if requested
+    pub fn body(&self) -> Result<Option<Vec<u8>>> {
+        self.data.as_ref().or(self.body.as_ref()).map(|d| read_data(d)).transpose()
+    }
+}
+
+/// read a value given on the command line. `@file` reads it from a file, and `@-` from stdin.
+pub fn read_data(data: &str) -> Result<Vec<u8>> {
+    let contents = match data.strip_prefix('@') {
+        Some("-") => {
+            let mut buf = Vec::new();
+            std::io::stdin().lock().read_to_end(&mut buf).context("error reading stdin")?;
+            buf
+        },
+        Some(path) => {
+            let expanded = shellexpand::tilde(path).to_string();
+            std::fs::read(&expanded).with_context(|| format!("error reading {}", expanded))?
+        },
+        None => data.as_bytes().to_vec()
+    };
+    Ok(contents)
+}
+
+pub fn parse_method(raw: &str) -> Result<Method> {
+    Method::from_bytes(raw.to_uppercase().as_bytes()).map_err(|_| anyhow!("invalid HTTP method '{}'", raw))
+}
+
+fn parse_header(raw: &str) -> Result<(String, String)> {
+    let (name, value) = raw.split_once(':').ok_or_else(|| anyhow!("header '{}' should be in the form 'Name: value'", raw))?;
+    Ok((name.trim().to_string(), value.trim().to_string()))
+}
+
+pub fn parse_param(raw: &str) -> Result<(String, String)> {
+    let (key, value) = raw.split_once('=').ok_or_else(|| anyhow!("parameter '{}' should be in the form key=value", raw))?;
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// the longest a pooled project can be checked out for
+const MAX_LEASE: std::time::Duration = std::time::Duration::from_secs(30 * 24 * 60 * 60);
+
+fn parse_lease(raw: &str) -> Result<humantime::Duration> {
+    let lease: humantime::Duration = raw.parse()?;
+    if *lease > MAX_LEASE {
+        return Err(anyhow!("lease '{}' is longer than the maximum of {}", raw, humantime::format_duration(MAX_LEASE)))
+    }
+    Ok(lease)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_ids, parse_method, parse_header, parse_param, parse_lease};
+
+    #[test]
+    fn test_read_ids() {
+        let input = "abc\n\n  def  \r\nghi";
+        assert_eq!(read_ids(input.as_bytes()).unwrap(), vec!["abc", "def", "ghi"]);
+    }
+
+    #[test]
+    fn test_parse_raw_args() {
+        assert_eq!(parse_method("patch").unwrap(), reqwest::Method::PATCH);
+        assert!(parse_method("not a method").is_err());
+        assert_eq!(parse_header("Content-Type: application/x-ndjson").unwrap(), ("Content-Type".to_string(), "application/x-ndjson".to_string()));
+        assert!(parse_header("Content-Type").is_err());
+        assert_eq!(parse_param("q=name=test").unwrap(), ("q".to_string(), "name=test".to_string()));
+        assert!(parse_param("q").is_err());
+    }
+
+    #[test]
+    fn test_parse_lease() {
+        assert_eq!(*parse_lease("2h").unwrap(), std::time::Duration::from_secs(2 * 60 * 60));
+        assert!(parse_lease("30days").is_ok());
+        assert!(parse_lease("100000y").is_err());
+        assert!(parse_lease("soon").is_err());
+    }
+}
@@ -1,11 +1,11 @@
-use std:: fs::read_to_string;
+use std::{fs::read_to_string, time::Instant};
 use anyhow::{Result, Context, Ok, anyhow};
-use log::{debug, error};
+use log::{debug, error, trace, log_enabled, Level};
 use regex::Regex;
-use reqwest::{header::{HeaderMap, HeaderValue}, blocking::Response};
+use reqwest::{header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE}, blocking::RequestBuilder, Method};
 use url::Url;
 
-use crate::config::Config;
+use crate::{config::Config, redact};
 
 use super::{serverless::ServerlessClient, stateful::StatefulClient};
 
@@ -15,11 +15,91 @@ pub trait ResultFormatting {
     fn compact(&self) -> String;
 }
 
+/// Declares the columns used to display project data as a table
+pub trait TableFormatting {
+    /// the column headers
+    fn headers(&self) -> Vec<String>;
+    /// the table rows, each with one value per header
+    fn rows(&self) -> Vec<Vec<String>>;
+}
+
+/// Lets list-oriented output formats (csv, tsv, ndjson) print one row per item of a list response.
+/// By default, the whole result is printed as a single row.
+pub trait ListFormatting: serde::Serialize {
+    fn list_rows(&self) -> Result<Vec<serde_json::Value>> {
+        Ok(vec![serde_json::to_value(self)?])
+    }
+}
+
+/// helper for converting a list of items into output rows
+pub fn list_rows<T: serde::Serialize>(items: &[T]) -> Result<Vec<serde_json::Value>> {
+    items.iter().map(|i| Ok(serde_json::to_value(i)?)).collect()
+}
+
+/// helper for building table headers from string literals
+pub fn headers(names: &[&str]) -> Vec<String> {
+    names.iter().map(|n| n.to_string()).collect()
+}
+
+/// Returned when the server responds with an error status code.
+/// Callers can downcast to this to react to specific codes, such as a 404.
+#[derive(Debug)]
+pub struct HttpStatusError {
+    pub status: reqwest::StatusCode
+}
+
+impl std::fmt::Display for HttpStatusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "got error from server: {}", self.status)
+    }
+}
+
+impl std::error::Error for HttpStatusError {}
+
+/// returns true if the error came from a 404 response
+pub fn is_not_found(err: &anyhow::Error) -> bool {
+    err.chain().any(|e| matches!(e.downcast_ref::<HttpStatusError>(), Some(he) if he.status == reqwest::StatusCode::NOT_FOUND))
+}
+
+/// Returned instead of sending a request when --dry-run or --print-curl is set.
+/// The request has already been printed, so callers should treat this as success.
+#[derive(Debug)]
+pub struct DryRun;
+
+impl std::fmt::Display for DryRun {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "request not sent due to --dry-run")
+    }
+}
+
+impl std::error::Error for DryRun {}
+
+/// returns true if the error came from a request skipped by --dry-run or --print-curl
+pub fn is_dry_run(err: &anyhow::Error) -> bool {
+    err.chain().any(|e| e.downcast_ref::<DryRun>().is_some())
+}
+
+/// How mutating requests are previewed instead of being sent
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Preview {
+    /// print the method, URL and body
+    Request,
+    /// print an equivalent curl command
+    Curl
+}
+
+/// the environment variable --print-curl commands read the API key from
+pub const CURL_KEY_VAR: &str = "ESSCLI_API_KEY";
+/// the environment variables --print-curl commands read data-plane credentials from
+pub const CURL_USER_VAR: &str = "ESSCLI_USERNAME";
+pub const CURL_PASSWORD_VAR: &str = "ESSCLI_PASSWORD";
+
 /// a base ESS client handler, capable of serverless and stateful api calls
 pub struct ESSClient {
     client: reqwest::blocking::Client,
     auth_req: HeaderMap,
-    endpoints: Config
+    endpoints: Config,
+    preview: Option<Preview>
 }
 
 impl ESSClient {
@@ -30,23 +110,37 @@ impl ESSClient {
 
         let mut headers = HeaderMap::new();
         let key_auth = format!("ApiKey {}", key);
+        redact::register(&key);
+        let mut auth = HeaderValue::from_str(&key_auth)?;
+        auth.set_sensitive(true);
         headers.insert("Content-Type", HeaderValue::from_str("application/json")?);
-        headers.insert("Authorization", HeaderValue::from_str(&key_auth)?);
+        headers.insert("Authorization", auth);
 
         Ok(ESSClient { client: reqwest::blocking::Client::new(), 
             endpoints: endpoint,
-            auth_req: headers})
+            auth_req: headers,
+            preview: None})
+    }
+    /// print requests that would change anything instead of sending them
+    pub fn set_preview(&mut self, preview: Option<Preview>) {
+        self.preview = preview;
     }
     /// return a serverless handler for the client
-    pub fn serverless(&self) -> Result<ServerlessClient> {
+    pub fn serverless(&self) -> Result<ServerlessClient<'_>> {
         let cfg = self.endpoints.resolve_serverless();
         let full_path = Url::parse(&cfg.url)?.join(&cfg.base_path)?;
         Ok(ServerlessClient { client: self, 
             base_url: full_path, 
             project: self.endpoints.config.project.clone()})
     }
+    /// return a serverless handler for a project type other than the one set in the config
+    pub fn serverless_project(&self, project: &str) -> Result<ServerlessClient<'_>> {
+        let mut handler = self.serverless()?;
+        handler.project = project.to_string();
+        Ok(handler)
+    }
     /// return a traditional stateful handler for the deployment
-    pub fn stateful(&self) -> Result<StatefulClient> {
+    pub fn stateful(&self) -> Result<StatefulClient<'_>> {
         let cfg = self.endpoints.resolve_stateful();
         let full_path = Url::parse(&cfg.url)?.join(&cfg.base_path)?;
 
@@ -57,46 +151,215 @@ impl ESSClient {
     pub fn get(&self, endpoint: &Url, relative_url: &str) -> Result<String>{
         let full_endpoint = endpoint.join(relative_url)?;
         debug!("GET: {}", full_endpoint);
-        let mut res = self.client.get(full_endpoint).headers(self.auth_req.clone()).send()?;
-        self.handle_http_error(&mut res)?;
-        Ok(res.text()?)
+        self.send(self.builder(Method::GET, full_endpoint))
     }
 
     /// Performs a POST request to ESS, checks the result, returns a string
     pub fn post(&self, endpoint: &Url, relative_url: &str, body: Option<String>) -> Result<String> {
+        self.send_body(Method::POST, endpoint, relative_url, body)
+    }
+
+    /// Performs a PUT request to ESS, checks the result, returns a string
+    pub fn put(&self, endpoint: &Url, relative_url: &str, body: Option<String>) -> Result<String> {
+        self.send_body(Method::PUT, endpoint, relative_url, body)
+    }
+
+    /// Performs a PATCH request to ESS, checks the result, returns a string
+    pub fn patch(&self, endpoint: &Url, relative_url: &str, body: Option<String>) -> Result<String> {
+        self.send_body(Method::PATCH, endpoint, relative_url, body)
+    }
+
+    fn send_body(&self, method: Method, endpoint: &Url, relative_url: &str, body: Option<String>) -> Result<String> {
         let full_endpoint = endpoint.join(relative_url)?;
-        let mut req = self.client.post(full_endpoint.clone()).headers(self.auth_req.clone());
+        let mut req = self.builder(method.clone(), full_endpoint.clone());
         if let Some(req_body) = body {
             req = req.body(req_body)
         }
-        debug!("POST: {}", full_endpoint);
-        let mut res = req.send()?;
-        self.handle_http_error(&mut res)?;
-        Ok(res.text()?)
+        debug!("{}: {}", method, full_endpoint);
+        self.check_preview(&req)?;
+        self.send(req)
     }
 
     /// Performs a DELETE request to ESS
     pub fn delete(&self, endpoint: &Url, relative_url: &str) -> Result<String> {
         let full_endpoint = endpoint.join(relative_url)?;
         debug!("DELETE: {}", full_endpoint);
-        let mut res = self.client.delete(full_endpoint).headers(self.auth_req.clone()).send()?;
-        self.handle_http_error(&mut res)?;
-        Ok(res.text()?)
-    }
-
-    fn handle_http_error(&self, resp: &mut Response) -> Result<()> {
-        let status = resp.status();
-        if status.is_client_error() || status.is_server_error() {
-            let mut buf: Vec<u8> = vec![];
-            resp.copy_to(&mut buf)?;
-            let string_resp = String::from_utf8_lossy(&buf);
-            error!("got error response ({}) from server: \n{}", status, string_resp);
-            return Err(anyhow!("got error from server: {}", status));
+        let req = self.builder(Method::DELETE, full_endpoint);
+        self.check_preview(&req)?;
+        self.send(req)
+    }
+
+    /// Performs a request with any method, extra headers and query parameters, returning the response whatever its status.
+    /// Requests other than GET and HEAD are previewed instead of sent with --dry-run.
+    pub fn request(&self, endpoint: &Url, parts: RequestParts) -> Result<RawResponse> {
+        let full_endpoint = endpoint.join(&parts.path)?;
+        debug!("{}: {}", parts.method, full_endpoint);
+        let req = self.builder(parts.method.clone(), full_endpoint);
+        self.send_parts(req, parts)
+    }
+
+    /// Performs a request against the API of a project or deployment, such as Elasticsearch,
+    /// authenticating as a user instead of with the API key
+    pub fn basic_auth_request(&self, endpoint: &Url, username: &str, password: &str, parts: RequestParts) -> Result<RawResponse> {
+        let full_endpoint = endpoint.join(&parts.path)?;
+        debug!("{}: {}", parts.method, full_endpoint);
+        let req = self.client.request(parts.method.clone(), full_endpoint)
+            .basic_auth(username, Some(password))
+            .header(CONTENT_TYPE, "application/json");
+        self.send_parts(req, parts)
+    }
+
+    /// Performs a request against a public endpoint that takes no credentials, such as a Fleet server's status API.
+    /// The API key is never sent.
+    pub fn plain_request(&self, endpoint: &Url, parts: RequestParts) -> Result<RawResponse> {
+        let full_endpoint = endpoint.join(&parts.path)?;
+        debug!("{}: {}", parts.method, full_endpoint);
+        let req = self.client.request(parts.method.clone(), full_endpoint);
+        self.send_parts(req, parts)
+    }
+
+    fn send_parts(&self, req: RequestBuilder, parts: RequestParts) -> Result<RawResponse> {
+        let mut req = req.query(&parts.params).headers(parts.headers);
+        if let Some(req_body) = parts.body {
+            req = req.body(req_body)
         }
-        Ok(())
+        self.check_preview(&req)?;
+        self.execute(req)
+    }
+
+    /// start a request with the auth headers set
+    fn builder(&self, method: Method, url: Url) -> RequestBuilder {
+        self.client.request(method, url).headers(self.auth_req.clone())
+    }
+
+    /// print the request and return a DryRun error if previews are enabled
+    fn check_preview(&self, req: &RequestBuilder) -> Result<()> {
+        let Some(preview) = self.preview else {
+            return Ok(())
+        };
+        let req = req.try_clone().ok_or_else(|| anyhow!("cannot preview a streaming request"))?.build()?;
+        // read-only requests are always sent
+        if req.method() == Method::GET || req.method() == Method::HEAD {
+            return Ok(())
+        }
+        let body = req.body().and_then(|b| b.as_bytes()).map(|b| String::from_utf8_lossy(b).to_string());
+        match preview {
+            Preview::Request => println!("{}", format_request(req.method().as_str(), req.url(), body.as_deref())),
+            Preview::Curl => println!("{}", format_curl(req.method().as_str(), req.url(), req.headers(), body.as_deref()))
+        }
+        Err(DryRun.into())
+    }
+
+    /// send a request and return the response body, failing on error status codes
+    fn send(&self, req: RequestBuilder) -> Result<String> {
+        let res = self.execute(req)?;
+        let body = String::from_utf8_lossy(&res.body).to_string();
+        if res.status.is_client_error() || res.status.is_server_error() {
+            error!("got error response ({}) from server: \n{}", res.status, body);
+            return Err(HttpStatusError{status: res.status}.into());
+        }
+        Ok(body)
+    }
+
+    /// send a request, logging the full request and response at trace level
+    fn execute(&self, req: RequestBuilder) -> Result<RawResponse> {
+        let req = req.build()?;
+        let (method, url) = (req.method().clone(), req.url().clone());
+        // secrets in either body must be known before anything is logged, not only once the result is printed
+        if let Some(body) = req.body().and_then(|b| b.as_bytes()) {
+            redact::register_body(body);
+        }
+        if log_enabled!(Level::Trace) {
+            let body = req.body().and_then(|b| b.as_bytes()).map(String::from_utf8_lossy).unwrap_or_default();
+            trace!("request: {} {}\n{}{}", method, url, format_headers(req.headers()), truncate_body(&body));
+        }
+
+        let start = Instant::now();
+        let res = self.client.execute(req)?;
+        let status = res.status();
+        let headers = res.headers().clone();
+        let body = res.bytes()?.to_vec();
+        redact::register_body(&body);
+        trace!("response: {} {} {} in {}ms\n{}{}", status, method, url, start.elapsed().as_millis(),
+            format_headers(&headers), truncate_body(&String::from_utf8_lossy(&body)));
+        Ok(RawResponse { status, headers, body })
     }
 }
 
+/// A request built from user input, e.g. by `raw`
+#[derive(Debug, Clone)]
+pub struct RequestParts {
+    pub method: Method,
+    /// the path, relative to the endpoint
+    pub path: String,
+    /// URL query parameters
+    pub params: Vec<(String, String)>,
+    /// headers added to, or replacing, the defaults
+    pub headers: HeaderMap,
+    pub body: Option<Vec<u8>>
+}
+
+/// The status, headers and unmodified body of a response
+#[derive(Debug)]
+pub struct RawResponse {
+    pub status: reqwest::StatusCode,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>
+}
+
+/// format a request as its method and URL, followed by the pretty-printed body
+pub fn format_request(method: &str, url: &Url, body: Option<&str>) -> String {
+    let mut out = format!("{} {}", method, url);
+    if let Some(body) = body.filter(|b| !b.is_empty()) {
+        out.push('\n');
+        out.push_str(&jsonxf::pretty_print(body).unwrap_or_else(|_| body.to_string()));
+    }
+    out
+}
+
+/// format a request as a curl command. The Authorization header reads the API key from the environment.
+pub fn format_curl(method: &str, url: &Url, headers: &HeaderMap, body: Option<&str>) -> String {
+    let mut out = format!("curl -X {} {}", method, shell_quote(url.as_str()));
+    for (name, val) in headers {
+        if name == AUTHORIZATION && val.as_bytes().starts_with(b"Basic ") {
+            out.push_str(&format!(" -u \"${}:${}\"", CURL_USER_VAR, CURL_PASSWORD_VAR));
+        } else if name == AUTHORIZATION {
+            out.push_str(&format!(" -H \"Authorization: ApiKey ${}\"", CURL_KEY_VAR));
+        } else {
+            out.push_str(&format!(" -H {}", shell_quote(&format!("{}: {}", name, String::from_utf8_lossy(val.as_bytes())))));
+        }
+    }
+    if let Some(body) = body.filter(|b| !b.is_empty()) {
+        let compact = jsonxf::minimize(body).unwrap_or_else(|_| body.to_string());
+        out.push_str(&format!(" -d {}", shell_quote(&compact)));
+    }
+    out
+}
+
+/// quote a value for a POSIX shell
+fn shell_quote(val: &str) -> String {
+    format!("'{}'", val.replace('\'', r"'\''"))
+}
+
+/// bodies longer than this are cut short in trace logs
+const MAX_TRACE_BODY: usize = 4096;
+
+/// format headers one per line, masking sensitive values such as the Authorization header
+fn format_headers(headers: &HeaderMap) -> String {
+    headers.iter().map(|(name, val)| {
+        let val = if val.is_sensitive() { redact::MASK.to_string() } else { String::from_utf8_lossy(val.as_bytes()).to_string() };
+        format!("{}: {}\n", name, val)
+    }).collect()
+}
+
+fn truncate_body(body: &str) -> String {
+    if body.chars().count() <= MAX_TRACE_BODY {
+        return body.to_string()
+    }
+    let cut: String = body.chars().take(MAX_TRACE_BODY).collect();
+    format!("{}... ({} bytes total)", cut, body.len())
+}
+
 /// check to see if a user-supplied ID value is valid
 pub fn check_id(id: &str) -> Result<()> {
     let re = Regex::new(r"^[a-z0-9-]{32,36}$").unwrap();
@@ -104,4 +367,27 @@ pub fn check_id(id: &str) -> Result<()> {
         true => Ok(()),
         false => Err(anyhow!("ID '{}' does not appear to be an actual ID value. Did you supply a name value instead?", id))
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    use super::{format_curl, format_request};
+
+    #[test]
+    fn test_format_preview() {
+        let url = Url::parse("https://api.example.com/api/v1/serverless/projects/elasticsearch").unwrap();
+        let body = r#"{"name": "it's a test", "region_id": "aws-eu-west-1"}"#;
+
+        assert_eq!(format_request("DELETE", &url, None), "DELETE https://api.example.com/api/v1/serverless/projects/elasticsearch");
+        assert!(format_request("POST", &url, Some(body)).starts_with("POST https://api.example.com/api/v1/serverless/projects/elasticsearch\n{\n"));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("Content-Type", HeaderValue::from_static("application/json"));
+        headers.insert("Authorization", HeaderValue::from_static("ApiKey secret"));
+        assert_eq!(format_curl("POST", &url, &headers, Some(body)),
+            r#"curl -X POST 'https://api.example.com/api/v1/serverless/projects/elasticsearch' -H 'content-type: application/json' -H "Authorization: ApiKey $ESSCLI_API_KEY" -d '{"name":"it'\''s a test","region_id":"aws-eu-west-1"}'"#);
+    }
+}
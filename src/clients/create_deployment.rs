@@ -7,7 +7,7 @@
 
 use serde::{Serialize, Deserialize};
 
-use super::deployments::{DeploymentMetadata, DeploymentObservabilitySettings, TrafficFilterSettings, DeploymentResource};
+use super::{deployments::{DeploymentMetadata, DeploymentObservabilitySettings, TrafficFilterSettings, DeploymentResource}, client::{ResultFormatting, TableFormatting, ListFormatting, headers}};
 
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -31,6 +31,23 @@ pub struct DeploymentCreateResponse {
     pub resources: Vec<DeploymentResource>,
 }
 
+impl ResultFormatting for DeploymentCreateResponse {
+    fn compact(&self) -> String {
+        format!("{}, {}, created: {}", self.name, self.id, self.created)
+    }
+}
+
+impl ListFormatting for DeploymentCreateResponse {}
+
+impl TableFormatting for DeploymentCreateResponse {
+    fn headers(&self) -> Vec<String> {
+        headers(&["ID", "NAME", "CREATED"])
+    }
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![self.id.clone(), self.name.clone(), self.created.to_string()]]
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct DeploymentDiagnostics {
     #[serde(rename = "creates", skip_serializing_if = "Option::is_none")]
@@ -200,6 +217,80 @@ pub struct DeploymentCreateRequest {
     pub metadata: Option<DeploymentMetadata>,
 }
 
+impl DeploymentCreateRequest {
+    /// the version the deployment will run, from the request or its Elasticsearch plan
+    pub fn elasticsearch_version(&self) -> Option<String> {
+        self.version.clone().or_else(|| self.resources.as_ref()?.elasticsearch.as_ref()?.first()?.plan.elasticsearch.version.clone())
+    }
+
+    /// set the version of the deployment, and of every Elasticsearch, Kibana and Integrations Server plan.
+    /// Update requests have no top-level version, so the plans need it too.
+    pub fn set_version(&mut self, version: &str) {
+        self.version = Some(version.to_string());
+        let Some(resources) = self.resources.as_mut() else {
+            return
+        };
+        for es in resources.elasticsearch.iter_mut().flatten() {
+            es.plan.elasticsearch.version = Some(version.to_string());
+        }
+        for kb in resources.kibana.iter_mut().flatten() {
+            kb.plan.kibana.version = Some(version.to_string());
+        }
+        for is in resources.integrations_server.iter_mut().flatten() {
+            is.plan.integrations_server.version = Some(version.to_string());
+        }
+    }
+
+    /// the docker image override of a component, one of `elasticsearch`, `kibana` or `integrations_server`
+    pub fn docker_image(&self, component: &str) -> Option<String> {
+        let resources = self.resources.as_ref()?;
+        match component {
+            "elasticsearch" => resources.elasticsearch.as_ref()?.first()?.plan.elasticsearch.docker_image.clone(),
+            "kibana" => resources.kibana.as_ref()?.first()?.plan.kibana.docker_image.clone(),
+            "integrations_server" => resources.integrations_server.as_ref()?.first()?.plan.integrations_server.docker_image.clone(),
+            _ => None
+        }
+    }
+
+    /// override the docker image of a component, one of `elasticsearch`, `kibana` or `integrations_server`
+    pub fn set_docker_image(&mut self, component: &str, image: &str) {
+        let Some(resources) = self.resources.as_mut() else {
+            return
+        };
+        let image = Some(image.to_string());
+        match component {
+            "elasticsearch" => resources.elasticsearch.iter_mut().flatten().for_each(|r| r.plan.elasticsearch.docker_image = image.clone()),
+            "kibana" => resources.kibana.iter_mut().flatten().for_each(|r| r.plan.kibana.docker_image = image.clone()),
+            "integrations_server" => resources.integrations_server.iter_mut().flatten().for_each(|r| r.plan.integrations_server.docker_image = image.clone()),
+            _ => ()
+        }
+    }
+}
+
+/// The body of a deployment update request. Resources are taken from the plans the deployment currently runs,
+/// as JSON, so fields this client doesn't model are sent back unchanged.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DeploymentUpdateRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Whether to remove resources that are in the deployment but not in the request
+    pub prune_orphans: bool,
+    /// resource payloads keyed by kind, e.g. `elasticsearch`, in the same shape as those of a create request
+    pub resources: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<DeploymentMetadata>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DeploymentUpdateResponse {
+    /// The id of the deployment
+    pub id: String,
+    /// The name of the deployment
+    pub name: String,
+    /// List of updated resources
+    pub resources: Vec<DeploymentResource>,
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct DeploymentCreateSettings {
     /// Enable autoscaling for this deployment.
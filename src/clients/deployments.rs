@@ -1,6 +1,8 @@
 use serde::{Serialize, Deserialize};
 
-use super::client::ResultFormatting;
+use crate::filter::Fields;
+
+use super::client::{ResultFormatting, TableFormatting, ListFormatting, headers, list_rows};
 
 // ***********************************************************************
 // structs for deployment listing/getting
@@ -21,6 +23,21 @@ impl ResultFormatting for DeploymentsList {
     }
 }
 
+impl ListFormatting for DeploymentsList {
+    fn list_rows(&self) -> anyhow::Result<Vec<serde_json::Value>> {
+        list_rows(&self.deployments)
+    }
+}
+
+impl TableFormatting for DeploymentsList {
+    fn headers(&self) -> Vec<String> {
+        headers(LISTING_HEADERS)
+    }
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.deployments.iter().flat_map(|d| d.rows()).collect()
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct DeploymentsListingData {
     /// The id of this deployment
@@ -37,6 +54,31 @@ impl ResultFormatting for DeploymentsListingData {
     }
 }
 
+const LISTING_HEADERS: &[&str] = &["ID", "NAME", "REGION"];
+
+impl TableFormatting for DeploymentsListingData {
+    fn headers(&self) -> Vec<String> {
+        headers(LISTING_HEADERS)
+    }
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![self.id.clone(), self.name.clone(), self.field("region").unwrap_or_default()]]
+    }
+}
+
+impl Fields for DeploymentsListingData {
+    const NAMES: &'static [&'static str] = &["id", "name", "region"];
+
+    fn field(&self, name: &str) -> Option<String> {
+        match name {
+            "id" => Some(self.id.clone()),
+            "name" => Some(self.name.clone()),
+            // the listing has no deployment-level region, so use the first resource that reports one
+            "region" => self.resources.iter().find_map(|r| r.region.clone()),
+            _ => None
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct DeploymentGetResponse {
     /// The name of this deployment
@@ -49,16 +91,118 @@ pub struct DeploymentGetResponse {
     pub observability: Option<DeploymentObservability>,
     /// A randomly-generated id of this Deployment
     pub id: String,
-    pub resources: DeploymentResource,
+    pub resources: DeploymentResources,
     pub metadata: Option<DeploymentMetadata>,
 }
 
+impl DeploymentGetResponse {
+    /// the URL of the deployment's Elasticsearch cluster, if it has been assigned one
+    pub fn elasticsearch_url(&self) -> Option<String> {
+        self.resources.elasticsearch.first().and_then(ResourceInfo::url)
+    }
+    /// the URL of the deployment's Kibana instance, if it has been assigned one
+    pub fn kibana_url(&self) -> Option<String> {
+        self.resources.kibana.first().and_then(ResourceInfo::url)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Default)]
+pub struct DeploymentResources {
+    /// The Elasticsearch clusters of the deployment
+    #[serde(default)]
+    pub elasticsearch: Vec<ResourceInfo>,
+    /// The Kibana instances of the deployment
+    #[serde(default)]
+    pub kibana: Vec<ResourceInfo>,
+    /// The APM instances of the deployment
+    #[serde(default)]
+    pub apm: Vec<ResourceInfo>,
+    /// The Integrations Server instances of the deployment
+    #[serde(default)]
+    pub integrations_server: Vec<ResourceInfo>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ResourceInfo {
+    /// A locally-unique user-specified id for the resource
+    pub ref_id: String,
+    /// The id of the resource
+    pub id: String,
+    /// The region the resource runs in
+    pub region: Option<String>,
+    /// The Elasticsearch cluster a Kibana, APM or Integrations Server resource belongs to
+    pub elasticsearch_cluster_ref_id: Option<String>,
+    pub info: Option<ResourceInfoDetails>,
+}
+
+impl ResourceInfo {
+    /// the HTTPS URL of the resource, preferring the aliased URL
+    pub fn url(&self) -> Option<String> {
+        let meta = self.info.as_ref()?.metadata.as_ref()?;
+        meta.aliased_url.clone()
+            .or_else(|| meta.service_url.clone())
+            .or_else(|| meta.endpoint.as_ref().map(|e| format!("https://{}", e)))
+    }
+
+    /// the plan the resource is currently running
+    pub fn current_plan(&self) -> Option<&serde_json::Value> {
+        self.info.as_ref()?.plan_info.as_ref()?.current.as_ref()?.plan.as_ref()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ResourceInfoDetails {
+    /// Whether the resource is healthy
+    pub healthy: Option<bool>,
+    /// The current status of the resource, e.g. started or stopped
+    pub status: Option<String>,
+    pub metadata: Option<ClusterMetadata>,
+    pub plan_info: Option<PlanInfo>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlanInfo {
+    /// The plan the resource is currently running
+    pub current: Option<PlanAttempt>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlanAttempt {
+    /// The plan as JSON, in the same shape as the plan of a create request
+    pub plan: Option<serde_json::Value>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ClusterMetadata {
+    /// The DNS name of the resource
+    pub endpoint: Option<String>,
+    /// The DNS name of the resource, using the deployment alias
+    pub aliased_endpoint: Option<String>,
+    /// The URL of the resource
+    pub service_url: Option<String>,
+    /// The URL of the resource, using the deployment alias
+    pub aliased_url: Option<String>,
+    /// An encoded string that provides other Elastic services with the necessary information to connect to this resource
+    pub cloud_id: Option<String>,
+}
+
 impl ResultFormatting for DeploymentGetResponse {
     fn compact(&self) -> String {
         format!("{}/{}: healthy: {}", self.name, self.id, self.healthy)
     }
 }
 
+impl ListFormatting for DeploymentGetResponse {}
+
+impl TableFormatting for DeploymentGetResponse {
+    fn headers(&self) -> Vec<String> {
+        headers(&["ID", "NAME", "ALIAS", "HEALTHY"])
+    }
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![self.id.clone(), self.name.clone(), self.alias.clone().unwrap_or_default(), self.healthy.to_string()]]
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct DeploymentObservability {
     /// Whether the deployment observability is healthy or not (one or more of the subsections will have healthy: false)
@@ -156,6 +300,23 @@ pub struct ClusterCredentials {
     pub password: String,
 }
 
+impl ResultFormatting for ClusterCredentials {
+    fn compact(&self) -> String {
+        format!("{},{}", self.username, self.password)
+    }
+}
+
+impl ListFormatting for ClusterCredentials {}
+
+impl TableFormatting for ClusterCredentials {
+    fn headers(&self) -> Vec<String> {
+        headers(&["USERNAME", "PASSWORD"])
+    }
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![self.username.clone(), self.password.clone()]]
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct ReplyWarning {
     /// A human readable message describing the warning that occurred
@@ -179,6 +340,17 @@ impl ResultFormatting for DeploymentShutdownResponse{
     }
 }
 
+impl ListFormatting for DeploymentShutdownResponse {}
+
+impl TableFormatting for DeploymentShutdownResponse {
+    fn headers(&self) -> Vec<String> {
+        headers(&["ID", "NAME"])
+    }
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![self.id.clone(), self.name.clone()]]
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Orphaned {
     /// List of orphaned Enterprise Search resource ids
@@ -233,7 +405,11 @@ pub struct DeploymentObservabilitySettings {
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct DeploymentMetadata {
     /// Arbitrary user-defined metadata associated with this deployment
-    pub tags: Option<Vec<MetadataItem>>
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<MetadataItem>>,
+    /// Whether the deployment is hidden, which is the case after it's been shut down
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hidden: Option<bool>
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -242,4 +418,44 @@ pub struct MetadataItem {
     pub value: String,
     /// The metadata field name
     pub key: String,
-}
\ No newline at end of file
+}
+
+impl DeploymentMetadata {
+    /// the value of a metadata tag, if it's set
+    pub fn tag(&self, key: &str) -> Option<&str> {
+        self.tags.as_ref()?.iter().find(|t| t.key == key).map(|t| t.value.as_str())
+    }
+
+    /// set a metadata tag, replacing any existing one with the same key
+    pub fn set_tag(&mut self, key: &str, value: &str) {
+        let tags = self.tags.get_or_insert_with(Vec::new);
+        tags.retain(|t| t.key != key);
+        tags.push(MetadataItem { key: key.to_string(), value: value.to_string() });
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::DeploymentGetResponse;
+
+    #[test]
+    fn test_resource_urls() {
+        let raw = r#"{
+            "id": "abc", "name": "test", "healthy": true,
+            "resources": {
+                "elasticsearch": [{"ref_id": "main-elasticsearch", "id": "es1", "region": "gcp-us-central1",
+                    "info": {"healthy": true, "status": "started",
+                        "metadata": {"endpoint": "es1.us-central1.gcp.cloud.es.io", "service_url": "https://es1.us-central1.gcp.cloud.es.io"},
+                        "plan_info": {"current": {"plan": {"cluster_topology": [], "elasticsearch": {"version": "8.11.1"}}}}}}],
+                "kibana": [{"ref_id": "main-kibana", "id": "kb1",
+                    "info": {"metadata": {"endpoint": "kb1.us-central1.gcp.cloud.es.io"}}}],
+                "apm": []
+            }
+        }"#;
+        let dep: DeploymentGetResponse = serde_json::from_str(raw).unwrap();
+        assert_eq!(dep.elasticsearch_url().as_deref(), Some("https://es1.us-central1.gcp.cloud.es.io"));
+        assert_eq!(dep.kibana_url().as_deref(), Some("https://kb1.us-central1.gcp.cloud.es.io"));
+        assert_eq!(dep.resources.elasticsearch[0].ref_id, "main-elasticsearch");
+        assert_eq!(dep.resources.elasticsearch[0].current_plan().unwrap()["elasticsearch"]["version"], "8.11.1");
+        assert_eq!(dep.resources.kibana[0].current_plan(), None);
+    }
+}
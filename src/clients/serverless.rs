@@ -5,7 +5,9 @@ use log::debug;
 use serde::{Deserialize, Serialize};
 use anyhow::{Result, Ok}; 
 
-use super::client::{self, ResultFormatting, check_id};
+use crate::filter::Fields;
+
+use super::client::{self, ResultFormatting, TableFormatting, ListFormatting, headers, list_rows, check_id};
 
 /// Client for serverless ESS.
 /// Normally instatiated via the EssClient.serverless() call.
@@ -15,6 +17,9 @@ pub struct ServerlessClient<'a>{
     pub base_url: url::Url,
 }
 
+/// The serverless project types, each with its own API path
+pub const PROJECT_TYPES: &[&str] = &["elasticsearch", "observability", "security"];
+
 /// List of all serverless Projects
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ProjectsList {
@@ -31,6 +36,21 @@ impl ResultFormatting for ProjectsList {
     }
 }
 
+impl ListFormatting for ProjectsList {
+    fn list_rows(&self) -> Result<Vec<serde_json::Value>> {
+        list_rows(&self.items)
+    }
+}
+
+impl TableFormatting for ProjectsList {
+    fn headers(&self) -> Vec<String> {
+        headers(PROJECT_HEADERS)
+    }
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.items.iter().flat_map(|i| i.rows()).collect()
+    }
+}
+
 /// Data on an individual serverless project
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Project {
@@ -43,6 +63,9 @@ pub struct Project {
     pub endpoints: ProjectEndpoints,
     #[serde(alias = "type")]
     pub project_type: String,
+    /// component overrides, only reported for internal users
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overrides: Option<ProjectOverrides>,
 }
 
 impl ResultFormatting for Project{
@@ -51,6 +74,44 @@ impl ResultFormatting for Project{
     }
 }
 
+impl ListFormatting for Project {}
+
+const PROJECT_HEADERS: &[&str] = &["ID", "NAME", "TYPE", "REGION", "CREATED AT", "ELASTICSEARCH", "KIBANA"];
+
+impl TableFormatting for Project {
+    fn headers(&self) -> Vec<String> {
+        headers(PROJECT_HEADERS)
+    }
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![self.id.clone(), self.name.clone(), self.project_type.clone(), self.region_id.clone(),
+            self.metadata.created_at.clone(), self.endpoints.elasticsearch.clone(), self.endpoints.kibana.clone()]]
+    }
+}
+
+impl Fields for Project {
+    const NAMES: &'static [&'static str] = &["id", "name", "alias", "region", "region_id", "type", "project_type", "cloud_id",
+        "created_at", "created_by", "organization_id", "endpoints.elasticsearch", "endpoints.kibana", "endpoints.apm"];
+
+    fn field(&self, name: &str) -> Option<String> {
+        let val = match name {
+            "id" => &self.id,
+            "name" => &self.name,
+            "alias" => &self.alias,
+            "region" | "region_id" => &self.region_id,
+            "type" | "project_type" => &self.project_type,
+            "cloud_id" => &self.cloud_id,
+            "created_at" => &self.metadata.created_at,
+            "created_by" => &self.metadata.created_by,
+            "organization_id" => &self.metadata.organization_id,
+            "endpoints.elasticsearch" => &self.endpoints.elasticsearch,
+            "endpoints.kibana" => &self.endpoints.kibana,
+            "endpoints.apm" => &self.endpoints.apm,
+            _ => return None
+        };
+        Some(val.clone())
+    }
+}
+
 impl std::fmt::Display for Project {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:#?}", self)
@@ -74,7 +135,7 @@ pub struct ProjectEndpoints {
 }
 
 /// user/pass combination for a project.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ProjectCredentials {
     pub username: String,
     pub password: String
@@ -86,6 +147,17 @@ impl ResultFormatting for ProjectCredentials {
     }
 }
 
+impl ListFormatting for ProjectCredentials {}
+
+impl TableFormatting for ProjectCredentials {
+    fn headers(&self) -> Vec<String> {
+        headers(&["USERNAME", "PASSWORD"])
+    }
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![self.username.clone(), self.password.clone()]]
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ProjectStatus {
     pub phase: String
@@ -97,6 +169,17 @@ impl ResultFormatting for ProjectStatus {
     }
 }
 
+impl ListFormatting for ProjectStatus {}
+
+impl TableFormatting for ProjectStatus {
+    fn headers(&self) -> Vec<String> {
+        headers(&["PHASE"])
+    }
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![self.phase.clone()]]
+    }
+}
+
 /// Provides data on a cloud region
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Region{
@@ -112,8 +195,46 @@ impl ResultFormatting for Region{
     }
 }
 
+impl ListFormatting for Region {}
+
+impl ResultFormatting for Vec<Region> {
+    fn compact(&self) -> String {
+        let mut acc = String::new();
+        for item in self {
+            acc = format!("{}{}\n", acc, item.compact());
+        };
+        acc
+    }
+}
+
+impl ListFormatting for Vec<Region> {
+    fn list_rows(&self) -> Result<Vec<serde_json::Value>> {
+        list_rows(self)
+    }
+}
+
+const REGION_HEADERS: &[&str] = &["ID", "NAME", "CSP", "CSP REGION"];
+
+impl TableFormatting for Region {
+    fn headers(&self) -> Vec<String> {
+        headers(REGION_HEADERS)
+    }
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![self.id.clone(), self.name.clone(), self.csp.clone(), self.csp_region.clone()]]
+    }
+}
+
+impl TableFormatting for Vec<Region> {
+    fn headers(&self) -> Vec<String> {
+        headers(REGION_HEADERS)
+    }
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.iter().flat_map(|r| r.rows()).collect()
+    }
+}
+
 /// Overrides for components that can be set during project creation.
-#[derive(Serialize, Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
 pub struct ProjectOverrides {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub elasticsearch: Option<ApplicationOverride>,
@@ -123,13 +244,13 @@ pub struct ProjectOverrides {
     pub fleet: Option<ApplicationOverride>
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ApplicationOverride {
     pub docker_image: String
 }
 
 /// The body of a project create request 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct CreateProject {
     pub name: String,
     pub region_id: String,
@@ -137,6 +258,15 @@ pub struct CreateProject {
     pub overrides: Option<ProjectOverrides>
 }
 
+/// The body of a project update request. Unset fields are left as they are.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UpdateProject {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub overrides: Option<ProjectOverrides>
+}
+
 impl ServerlessClient<'_>{
     /// list all projects on the system
     pub fn list(&self) -> Result<ProjectsList> {
@@ -202,6 +332,16 @@ impl ServerlessClient<'_>{
         Ok(data)
     }
 
+    /// update a project's name or overrides
+    pub fn update(&self, id: &str, proj: UpdateProject) -> Result<Project> {
+        check_id(id)?;
+        let path = format!("projects/{}/{}", self.project, id);
+        let body = serde_json::to_string(&proj)?;
+        let res = self.client.patch(&self.base_url, &path, Some(body))?;
+        let data: Project = serde_json::from_str(&res)?;
+        Ok(data)
+    }
+
     /// List all available regions
     pub fn regions(&self) -> Result<Vec<Region>> {
         let res = self.client.get(&self.base_url, "regions")?;
@@ -221,6 +361,8 @@ mod tests {
         let cfg = Config{
             statefull_override: None,
             serverless_override: None,
+            formats: Default::default(),
+            recipes: Default::default(),
             config: crate::config::UserConfig {
                 default_deployment: "~/.config/ess/api_key".to_string(),
                  project: "observability".to_string(),
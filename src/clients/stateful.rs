@@ -1,6 +1,6 @@
 use anyhow::{Result, Context};
 use url::Url;
-use super::{client::{self, check_id}, deployments::{DeploymentsList, DeploymentGetResponse, DeploymentShutdownResponse}, create_deployment::{DeploymentCreateRequest, DeploymentCreateResponse}};
+use super::{client::{self, check_id}, deployments::{DeploymentsList, DeploymentGetResponse, DeploymentShutdownResponse, ClusterCredentials}, create_deployment::{DeploymentCreateRequest, DeploymentCreateResponse, DeploymentUpdateRequest, DeploymentUpdateResponse}};
 
 pub struct StatefulClient<'a>{
     pub client: &'a client::ESSClient,
@@ -33,6 +33,15 @@ impl StatefulClient<'_> {
         Ok(data)
     }
 
+    /// reset the password of the elastic user for one of the deployment's Elasticsearch clusters
+    pub fn reset_password(&self, id: &str, ref_id: &str) -> Result<ClusterCredentials> {
+        check_id(id)?;
+        let path = format!("deployments/{}/elasticsearch/{}/_reset-password", id, ref_id);
+        let res = self.client.post(&self.base_url, &path, None)?;
+        let data: ClusterCredentials = serde_json::from_str(&res).context("error reading response body")?;
+        Ok(data)
+    }
+
     /// create a deployment from the given config
     pub fn create(&self, dep: DeploymentCreateRequest) -> Result<DeploymentCreateResponse> {
         let path = String::from("deployments");
@@ -41,4 +50,14 @@ impl StatefulClient<'_> {
         let data: DeploymentCreateResponse = serde_json::from_str(&res).context("error reading response body")?;
         Ok(data)
     }
-}
\ No newline at end of file
+
+    /// update a deployment's resources, name or metadata
+    pub fn update(&self, id: &str, dep: DeploymentUpdateRequest) -> Result<DeploymentUpdateResponse> {
+        check_id(id)?;
+        let path = format!("deployments/{}", id);
+        let body = serde_json::to_string_pretty(&dep).context("error creating JSON from DeploymentUpdateRequest")?;
+        let res = self.client.put(&self.base_url, &path, Some(body))?;
+        let data: DeploymentUpdateResponse = serde_json::from_str(&res).context("error reading response body")?;
+        Ok(data)
+    }
+}
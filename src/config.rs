@@ -1,6 +1,6 @@
-use std::{fs::{read_to_string, File}, io::Write};
+use std::{collections::BTreeMap, fs::{self, read_to_string, File, OpenOptions}, io::{ErrorKind, Write}, path::{Path, PathBuf}, thread, time::{Duration, Instant, SystemTime}};
 use anyhow::{Result, anyhow, Context};
-use log::debug;
+use log::{debug, warn};
 use serde::{Deserialize, Serialize};
 
 #[derive(Deserialize, Serialize, Clone)]
@@ -8,7 +8,36 @@ pub struct Config {
     pub config: UserConfig,
     pub defaults: TypeConfig,
     pub statefull_override: Option<OptionalTypeConfig>,
-    pub serverless_override: Option<OptionalTypeConfig>
+    pub serverless_override: Option<OptionalTypeConfig>,
+    /// named output templates that can be passed to --format
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub formats: BTreeMap<String, String>,
+    /// named raw requests that can be run with `esscli run`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub recipes: BTreeMap<String, Recipe>
+}
+
+/// A saved raw request. The path and body can contain `{name}` placeholders, filled from `--arg name=value`.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Recipe {
+    /// which API the request is sent to
+    pub target: RecipeTarget,
+    #[serde(default = "default_recipe_method")]
+    pub method: String,
+    pub path: String,
+    pub body: Option<String>,
+    pub description: Option<String>
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RecipeTarget {
+    Serverless,
+    Stateful
+}
+
+fn default_recipe_method() -> String {
+    "GET".to_string()
 }
 
 #[derive(Deserialize, Serialize, Clone, Default)]
@@ -48,10 +77,93 @@ impl From<TypeConfig> for DeploymentSpecificConfig {
     }
 }
 
+/// return the directory esscli keeps its config file and local state in
+pub fn config_dir() -> Result<PathBuf> {
+    let mut home = dirs::home_dir().ok_or_else(|| anyhow!("could not find home dir"))?;
+    home.push(".config/ess");
+    Ok(home)
+}
+
+/// write a file readable only by the current user, creating the parent directory if needed
+pub fn write_private_file(path: &Path, data: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context(format!("error creating directory {}", parent.display()))?;
+    }
+    // write to a temp file and rename, so a failed write can't truncate the existing file
+    let tmp = path.with_extension("tmp");
+    let mut opts = OpenOptions::new();
+    opts.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        opts.mode(0o600);
+    }
+    let mut file = opts.open(&tmp).context(format!("error opening {}", tmp.display()))?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&tmp, fs::Permissions::from_mode(0o600))?;
+    }
+    fs::rename(&tmp, path).context(format!("error writing {}", path.display()))?;
+    Ok(())
+}
+
+/// how long to wait for another esscli process to release a lock file
+const LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// a lock file older than this was left behind by a process that died, and is removed
+const LOCK_STALE_AFTER: Duration = Duration::from_secs(120);
+
+/// An exclusive lock file, held while a shared file is read, changed and written back. Released when dropped.
+pub struct FileLock {
+    path: PathBuf
+}
+
+impl FileLock {
+    pub fn acquire(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context(format!("error creating directory {}", parent.display()))?;
+        }
+        let start = Instant::now();
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(path) {
+                Result::Ok(mut file) => {
+                    writeln!(file, "{}", std::process::id())?;
+                    return Ok(FileLock { path: path.to_path_buf() })
+                },
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    let age = fs::metadata(path).and_then(|m| m.modified())
+                        .map(|m| SystemTime::now().duration_since(m).unwrap_or_default());
+                    if age.is_ok_and(|a| a > LOCK_STALE_AFTER) {
+                        warn!("removing stale lock {}", path.display());
+                        let _ = fs::remove_file(path);
+                        continue;
+                    }
+                    if start.elapsed() > LOCK_TIMEOUT {
+                        return Err(anyhow!("timed out waiting for the lock {}, remove it if no other esscli is running", path.display()))
+                    }
+                    debug!("waiting for lock {}", path.display());
+                    thread::sleep(Duration::from_millis(100));
+                },
+                Err(e) => return Err(anyhow!(e).context(format!("error creating lock {}", path.display())))
+            }
+        }
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            warn!("could not remove lock {}: {}", self.path.display(), e);
+        }
+    }
+}
+
 /// return a parsed config object
 pub fn get_config() -> Result<Config> {
-    let mut home = dirs::home_dir().ok_or_else(|| anyhow!("could not find home dir"))?;
-    home.push(".config/ess/esscli.toml");
+    let home = config_dir()?.join("esscli.toml");
 
     let cfg_raw = read_to_string(&home).context(format!("error reading config file at {}", home.display()))?;
     let cfg: Config = toml::from_str(&cfg_raw).context("error reading config file")?;
@@ -68,6 +180,8 @@ pub fn create_config(path: &str) -> Result<()> {
             },
             defaults: TypeConfig { url: "https://console.qa.cld.elstc.co".to_string(), base_path: "/api/v1/".to_string() },
             statefull_override: None,
+            formats: BTreeMap::new(),
+            recipes: BTreeMap::new(),
             serverless_override: Some(
                 OptionalTypeConfig{
                     url: Some("https://global.qa.cld.elstc.co".to_string()), 
@@ -94,6 +208,17 @@ pub fn create_config(path: &str) -> Result<()> {
 
 
 impl Config {
+    /// resolve a --format value, which is either the name of a format in the config file or a template
+    pub fn resolve_format<'a>(&'a self, format: &'a str) -> Result<&'a str> {
+        if let Some(named) = self.formats.get(format) {
+            return Ok(named)
+        }
+        if !format.contains("{{") {
+            return Err(anyhow!("format '{}' is not a template, and is not defined in the [formats] section of the config file", format))
+        }
+        Ok(format)
+    }
+
     /// resolve the endpoint config for serverless, taking into account overrides
     pub fn resolve_serverless(&self) -> DeploymentSpecificConfig {
         let mut found = DeploymentSpecificConfig::default();
@@ -342,6 +467,8 @@ mod tests {
             defaults: super::TypeConfig { url: "default-url".to_string(), 
             base_path: "default_pat".to_string() },
             serverless_override: None,
+            formats: Default::default(),
+            recipes: Default::default(),
             statefull_override: Some(super::OptionalTypeConfig { url: Some("stateful".to_string()), 
                 base_path: Some("stateful_path".to_string()), 
                 region: Some("stateful_region".to_string()) })
@@ -363,7 +490,9 @@ mod tests {
             serverless_override: Some(super::OptionalTypeConfig { url: Some("serverless".to_string()), 
             base_path: Some("serverless_path".to_string()), 
             region: Some("serverless_region".to_string()) }),
-            statefull_override: None
+            statefull_override: None,
+            formats: Default::default(),
+            recipes: Default::default()
         };
 
         let res = cfg.resolve_serverless();
@@ -371,4 +500,21 @@ mod tests {
         assert_eq!(res.url, String::from("serverless"));
         assert_eq!(res.region, Some(String::from("serverless_region")));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_resolve_format(){
+        let mut cfg = Config{
+            config: super::UserConfig::default(),
+            defaults: super::TypeConfig::default(),
+            serverless_override: None,
+            statefull_override: None,
+            formats: Default::default(),
+            recipes: Default::default()
+        };
+        cfg.formats.insert("short".to_string(), "{{.id}}".to_string());
+
+        assert_eq!(cfg.resolve_format("short").unwrap(), "{{.id}}");
+        assert_eq!(cfg.resolve_format("{{.name}}").unwrap(), "{{.name}}");
+        assert!(cfg.resolve_format("missing").is_err());
+    }
+}
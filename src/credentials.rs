@@ -0,0 +1,260 @@
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce, aead::{Aead, OsRng, rand_core::RngCore}};
+use anyhow::{Result, Context, anyhow};
+use argon2::Argon2;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::{clients::{client::{ResultFormatting, TableFormatting, ListFormatting}, serverless::ProjectCredentials, deployments::ClusterCredentials}, config::{FileLock, config_dir, write_private_file}};
+
+/// environment variable that holds the passphrase used to encrypt the credential store
+pub const PASSPHRASE_ENV: &str = "ESSCLI_PASSPHRASE";
+
+const STORE_FILE: &str = "credentials.json";
+
+/// credentials saved for a single serverless project or stateful deployment
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StoredCredentials {
+    Serverless(ProjectCredentials),
+    Stateful(ClusterCredentials)
+}
+
+impl ResultFormatting for StoredCredentials {
+    fn compact(&self) -> String {
+        match self {
+            StoredCredentials::Serverless(c) => c.compact(),
+            StoredCredentials::Stateful(c) => c.compact()
+        }
+    }
+}
+
+impl ListFormatting for StoredCredentials {}
+
+impl TableFormatting for StoredCredentials {
+    fn headers(&self) -> Vec<String> {
+        match self {
+            StoredCredentials::Serverless(c) => c.headers(),
+            StoredCredentials::Stateful(c) => c.headers()
+        }
+    }
+    fn rows(&self) -> Vec<Vec<String>> {
+        match self {
+            StoredCredentials::Serverless(c) => c.rows(),
+            StoredCredentials::Stateful(c) => c.rows()
+        }
+    }
+}
+
+/// on-disk representation of the store. The encrypted variant holds the
+/// same entry map, serialized to JSON and sealed with AES-256-GCM.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "format", rename_all = "snake_case")]
+enum StoreFile {
+    Plain{entries: BTreeMap<String, StoredCredentials>},
+    Encrypted{salt: String, nonce: String, data: String}
+}
+
+/// A local, permission-restricted store of credentials, keyed by resource ID.
+pub struct CredentialStore {
+    path: PathBuf,
+    passphrase: Option<String>,
+    entries: BTreeMap<String, StoredCredentials>,
+    /// entries inserted, or with None removed, since the store was opened
+    changes: BTreeMap<String, Option<StoredCredentials>>
+}
+
+impl CredentialStore {
+    /// open the store in the esscli config directory, using the passphrase from the environment if set
+    pub fn open_default() -> Result<Self> {
+        let path = config_dir()?.join(STORE_FILE);
+        let passphrase = std::env::var(PASSPHRASE_ENV).ok().filter(|p| !p.is_empty());
+        Self::open(path, passphrase)
+    }
+
+    /// open the store at the given path. A missing file results in an empty store.
+    pub fn open(path: PathBuf, passphrase: Option<String>) -> Result<Self> {
+        let mut store = CredentialStore { path, passphrase, entries: BTreeMap::new(), changes: BTreeMap::new() };
+        store.entries = store.read()?;
+        Ok(store)
+    }
+
+    /// read the entries currently on disk
+    fn read(&self) -> Result<BTreeMap<String, StoredCredentials>> {
+        if !self.path.exists() {
+            return Ok(BTreeMap::new())
+        }
+        let raw = fs::read_to_string(&self.path).context(format!("error reading credential store at {}", self.path.display()))?;
+        let file: StoreFile = serde_json::from_str(&raw).context("error parsing credential store")?;
+        match file {
+            StoreFile::Plain { entries } => Ok(entries),
+            StoreFile::Encrypted { salt, nonce, data } => {
+                let passphrase = self.passphrase.as_ref()
+                    .ok_or_else(|| anyhow!("credential store is encrypted, set {} to read it", PASSPHRASE_ENV))?;
+                let plain = decrypt(passphrase, &salt, &nonce, &data)?;
+                serde_json::from_slice(&plain).context("error parsing decrypted credential store")
+            }
+        }
+    }
+
+    /// fetch the credentials for a resource
+    pub fn get(&self, id: &str) -> Option<&StoredCredentials> {
+        self.entries.get(id)
+    }
+
+    /// add or replace the credentials for a resource
+    pub fn insert(&mut self, id: &str, creds: StoredCredentials) {
+        self.entries.insert(id.to_string(), creds.clone());
+        self.changes.insert(id.to_string(), Some(creds));
+    }
+
+    /// remove the credentials for a resource, returning them if they existed
+    pub fn remove(&mut self, id: &str) -> Option<StoredCredentials> {
+        self.changes.insert(id.to_string(), None);
+        self.entries.remove(id)
+    }
+
+    /// write this store's changes to disk, encrypting it if a passphrase is set. The file is re-read under a lock
+    /// and the changes applied on top, so credentials saved by another esscli process in the meantime are kept.
+    pub fn save(&self) -> Result<()> {
+        let _lock = FileLock::acquire(&self.path.with_extension("lock"))?;
+        let mut entries = self.read()?;
+        for (id, change) in &self.changes {
+            match change {
+                Some(creds) => entries.insert(id.clone(), creds.clone()),
+                None => entries.remove(id)
+            };
+        }
+        let file = match &self.passphrase {
+            Some(passphrase) => {
+                let plain = serde_json::to_vec(&entries)?;
+                let (salt, nonce, data) = encrypt(passphrase, &plain)?;
+                StoreFile::Encrypted { salt, nonce, data }
+            },
+            None => StoreFile::Plain { entries: entries.clone() }
+        };
+        let out = serde_json::to_string_pretty(&file)?;
+        write_private_file(&self.path, out.as_bytes())?;
+        debug!("wrote {} entries to credential store at {}", entries.len(), self.path.display());
+        Ok(())
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<[u8; 32]> {
+    let mut key = [0u8; 32];
+    Argon2::default().hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow!("error deriving key from passphrase: {}", e))?;
+    Ok(key)
+}
+
+fn encrypt(passphrase: &str, plain: &[u8]) -> Result<(String, String, String)> {
+    let mut salt = [0u8; 16];
+    let mut nonce = [0u8; 12];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+
+    let key = derive_key(passphrase, &salt)?;
+    let cipher = Aes256Gcm::new_from_slice(&key).map_err(|e| anyhow!("error creating cipher: {}", e))?;
+    let data = cipher.encrypt(Nonce::from_slice(&nonce), plain)
+        .map_err(|_| anyhow!("error encrypting credential store"))?;
+    Ok((STANDARD.encode(salt), STANDARD.encode(nonce), STANDARD.encode(data)))
+}
+
+fn decrypt(passphrase: &str, salt: &str, nonce: &str, data: &str) -> Result<Vec<u8>> {
+    let salt = STANDARD.decode(salt).context("invalid salt in credential store")?;
+    let nonce = STANDARD.decode(nonce).context("invalid nonce in credential store")?;
+    let data = STANDARD.decode(data).context("invalid data in credential store")?;
+    if nonce.len() != 12 {
+        return Err(anyhow!("invalid nonce length in credential store"))
+    }
+
+    let key = derive_key(passphrase, &salt)?;
+    let cipher = Aes256Gcm::new_from_slice(&key).map_err(|e| anyhow!("error creating cipher: {}", e))?;
+    cipher.decrypt(Nonce::from_slice(&nonce), data.as_ref())
+        .map_err(|_| anyhow!("could not decrypt credential store, is {} correct?", PASSPHRASE_ENV))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::clients::{serverless::ProjectCredentials, deployments::ClusterCredentials};
+
+    use super::{CredentialStore, StoredCredentials};
+
+    fn temp_store_path(name: &str) -> PathBuf {
+        let mut path = std::env::temp_dir();
+        path.push(format!("esscli-test-{}-{}", name, std::process::id()));
+        path.push("credentials.json");
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_plain_roundtrip() {
+        let path = temp_store_path("plain");
+        let mut store = CredentialStore::open(path.clone(), None).unwrap();
+        store.insert("abc", StoredCredentials::Serverless(ProjectCredentials { username: "admin".to_string(), password: "pass".to_string() }));
+        store.insert("def", StoredCredentials::Stateful(ClusterCredentials { username: "elastic".to_string(), password: "other".to_string() }));
+        store.save().unwrap();
+
+        let mut reopened = CredentialStore::open(path.clone(), None).unwrap();
+        assert!(matches!(reopened.get("abc"), Some(StoredCredentials::Serverless(c)) if c.password == "pass"));
+        assert!(matches!(reopened.get("def"), Some(StoredCredentials::Stateful(c)) if c.username == "elastic"));
+
+        reopened.remove("abc");
+        reopened.save().unwrap();
+        let reopened = CredentialStore::open(path.clone(), None).unwrap();
+        assert!(reopened.get("abc").is_none());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
+    #[test]
+    fn test_concurrent_save() {
+        let path = temp_store_path("concurrent");
+        let creds = |password: &str| StoredCredentials::Serverless(ProjectCredentials { username: "admin".to_string(), password: password.to_string() });
+        let mut seed = CredentialStore::open(path.clone(), None).unwrap();
+        seed.insert("old", creds("old-pass"));
+        seed.save().unwrap();
+
+        // two processes open the store before either saves
+        let mut first = CredentialStore::open(path.clone(), None).unwrap();
+        let mut second = CredentialStore::open(path.clone(), None).unwrap();
+        first.insert("abc", creds("first-pass"));
+        first.save().unwrap();
+        second.insert("def", creds("second-pass"));
+        second.remove("old");
+        second.save().unwrap();
+
+        let reopened = CredentialStore::open(path.clone(), None).unwrap();
+        assert!(matches!(reopened.get("abc"), Some(StoredCredentials::Serverless(c)) if c.password == "first-pass"));
+        assert!(matches!(reopened.get("def"), Some(StoredCredentials::Serverless(c)) if c.password == "second-pass"));
+        assert!(reopened.get("old").is_none());
+        assert!(!path.with_extension("lock").exists());
+    }
+
+    #[test]
+    fn test_encrypted_roundtrip() {
+        let path = temp_store_path("encrypted");
+        let mut store = CredentialStore::open(path.clone(), Some("hunter2".to_string())).unwrap();
+        store.insert("abc", StoredCredentials::Serverless(ProjectCredentials { username: "admin".to_string(), password: "secret-pass".to_string() }));
+        store.save().unwrap();
+
+        let raw = std::fs::read_to_string(&path).unwrap();
+        assert!(!raw.contains("secret-pass"));
+
+        let reopened = CredentialStore::open(path.clone(), Some("hunter2".to_string())).unwrap();
+        assert!(matches!(reopened.get("abc"), Some(StoredCredentials::Serverless(c)) if c.password == "secret-pass"));
+
+        assert!(CredentialStore::open(path.clone(), Some("wrong".to_string())).is_err());
+        assert!(CredentialStore::open(path, None).is_err());
+    }
+}
@@ -0,0 +1,172 @@
+use anyhow::{Result, Context, anyhow};
+use log::{debug, info};
+use reqwest::{Method, header::{HeaderMap, HeaderValue}};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use url::Url;
+
+use crate::{clients::{client::{ESSClient, HttpStatusError, RawResponse, RequestParts, is_not_found}, serverless::PROJECT_TYPES}, credentials::{CredentialStore, StoredCredentials}, redact, state::{Inventory, ResourceKind}};
+
+/// An application running in a project or deployment
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Service {
+    Elasticsearch,
+    Kibana
+}
+
+impl std::fmt::Display for Service {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Service::Elasticsearch => write!(f, "Elasticsearch"),
+            Service::Kibana => write!(f, "Kibana")
+        }
+    }
+}
+
+/// The endpoint of an application in a project or deployment, along with the credentials to call it
+pub struct DataPlaneTarget {
+    pub service: Service,
+    pub url: Url,
+    pub username: String,
+    pub password: String
+}
+
+/// find the endpoint of an application and the credentials for a serverless project or stateful deployment.
+/// Credentials come from the local store; if there are none, they're reset and the new ones are stored.
+pub fn resolve(client: &ESSClient, id: &str, service: Service) -> Result<DataPlaneTarget> {
+    let mut store = CredentialStore::open_default().context("error opening credential store")?;
+    let inv = Inventory::open_default().context("error opening local state file")?;
+    let entry = inv.entries().iter().find(|e| e.id == id);
+    let (kind, project_type) = match (entry, store.get(id)) {
+        (Some(entry), _) => (entry.kind, entry.project_type.clone()),
+        (None, Some(StoredCredentials::Serverless(_))) => (ResourceKind::Serverless, find_project_type(client, id)?),
+        (None, Some(StoredCredentials::Stateful(_))) => (ResourceKind::Stateful, None),
+        // not created from this machine, so ask the API
+        (None, None) => match find_project_type(client, id)? {
+            Some(project_type) => (ResourceKind::Serverless, Some(project_type)),
+            None => (ResourceKind::Stateful, None)
+        }
+    };
+
+    let mut reset = false;
+    let (url, creds) = match kind {
+        ResourceKind::Serverless => {
+            let sl = match project_type.as_deref() {
+                Some(project) => client.serverless_project(project)?,
+                None => client.serverless()?
+            };
+            let project = sl.get(id).context("error fetching project")?;
+            let url = match service {
+                Service::Elasticsearch => project.endpoints.elasticsearch,
+                Service::Kibana => project.endpoints.kibana
+            };
+            if url.is_empty() {
+                return Err(anyhow!("project {} has no {} endpoint yet", id, service))
+            }
+            let creds = match store.get(id) {
+                Some(creds @ StoredCredentials::Serverless(_)) => creds.clone(),
+                _ => {
+                    info!("no stored credentials for project {}, resetting them", id);
+                    reset = true;
+                    StoredCredentials::Serverless(sl.reset_credentials(id).context("error resetting credentials")?)
+                }
+            };
+            (url, creds)
+        },
+        ResourceKind::Stateful => {
+            let sf = client.stateful()?;
+            let dep = sf.get(id).context("error fetching deployment")?;
+            let url = match service {
+                Service::Elasticsearch => dep.elasticsearch_url(),
+                Service::Kibana => dep.kibana_url()
+            }.ok_or_else(|| anyhow!("deployment {} has no {} endpoint yet", id, service))?;
+            let creds = match store.get(id) {
+                Some(creds @ StoredCredentials::Stateful(_)) => creds.clone(),
+                _ => {
+                    let ref_id = &dep.resources.elasticsearch.first()
+                        .ok_or_else(|| anyhow!("deployment {} has no Elasticsearch cluster", id))?.ref_id;
+                    info!("no stored credentials for deployment {}, resetting the elastic user's password", id);
+                    reset = true;
+                    StoredCredentials::Stateful(sf.reset_password(id, ref_id).context("error resetting password")?)
+                }
+            };
+            (url, creds)
+        }
+    };
+
+    if reset {
+        store.insert(id, creds.clone());
+        store.save().context("error saving credentials to local store")?;
+    }
+    let (username, password) = match creds {
+        StoredCredentials::Serverless(c) => (c.username, c.password),
+        StoredCredentials::Stateful(c) => (c.username, c.password)
+    };
+    redact::register(&password);
+    Ok(DataPlaneTarget { service, url: Url::parse(&url).context(format!("invalid endpoint {}", url))?, username, password })
+}
+
+impl DataPlaneTarget {
+    /// send a request, returning the response whatever its status. Kibana requests get the kbn-xsrf header.
+    pub fn send(&self, client: &ESSClient, mut parts: RequestParts) -> Result<RawResponse> {
+        if self.service == Service::Kibana && !parts.headers.contains_key("kbn-xsrf") {
+            parts.headers.insert("kbn-xsrf", HeaderValue::from_static("true"));
+        }
+        client.basic_auth_request(&self.url, &self.username, &self.password, parts)
+    }
+
+    /// send a request with an optional JSON body, and parse the JSON response, failing on error status codes
+    pub fn call<T: DeserializeOwned>(&self, client: &ESSClient, method: Method, path: &str, body: Option<Value>) -> Result<T> {
+        let parts = RequestParts {
+            method: method.clone(),
+            path: path.to_string(),
+            params: Vec::new(),
+            headers: HeaderMap::new(),
+            body: body.map(|b| serde_json::to_vec(&b)).transpose()?
+        };
+        let res = self.send(client, parts)?;
+        let body = String::from_utf8_lossy(&res.body);
+        if res.status.is_client_error() || res.status.is_server_error() {
+            debug!("error response from {} {}: {}", method, path, body);
+            return Err(HttpStatusError{status: res.status}.into())
+        }
+        serde_json::from_str(&body).with_context(|| format!("error reading response from {} {}", method, path))
+    }
+}
+
+/// find the type of a serverless project by asking for it under each project type, starting with the one in the config
+/// file. Returns None if no project has the ID.
+fn find_project_type(client: &ESSClient, id: &str) -> Result<Option<String>> {
+    let configured = client.serverless()?.project;
+    let types = std::iter::once(configured.as_str()).chain(PROJECT_TYPES.iter().copied().filter(|t| *t != configured));
+    for project_type in types {
+        match client.serverless_project(project_type)?.get(id) {
+            Result::Ok(_) => return Ok(Some(project_type.to_string())),
+            Err(e) if is_not_found(&e) => continue,
+            Err(e) => return Err(e.context(format!("error looking up {} project {}", project_type, id)))
+        }
+    }
+    Ok(None)
+}
+
+/// prefix a Kibana API path with a space, e.g. `api/saved_objects/_find` becomes `s/my-space/api/saved_objects/_find`.
+/// The default space has no prefix.
+pub fn kibana_path(path: &str, space: Option<&str>) -> String {
+    let path = path.trim_start_matches('/');
+    match space {
+        Some(space) if space != "default" => format!("s/{}/{}", space, path),
+        _ => path.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::kibana_path;
+
+    #[test]
+    fn test_kibana_path() {
+        assert_eq!(kibana_path("/api/fleet/agent_policies", None), "api/fleet/agent_policies");
+        assert_eq!(kibana_path("api/saved_objects/_find", Some("testing")), "s/testing/api/saved_objects/_find");
+        assert_eq!(kibana_path("api/status", Some("default")), "api/status");
+    }
+}
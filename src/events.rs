@@ -0,0 +1,200 @@
+use std::{collections::BTreeMap, io::Write, thread, time::Duration};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde::Serialize;
+use serde_json::{Value, json};
+
+use crate::state::ResourceKind;
+
+/// The state of a single resource at the time it was polled
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    pub kind: ResourceKind,
+    pub id: String,
+    pub name: String,
+    /// the serverless project phase
+    pub phase: Option<String>,
+    /// the stateful deployment health
+    pub healthy: Option<bool>,
+    /// endpoint name to URL, only containing endpoints that have been populated
+    pub endpoints: BTreeMap<String, String>
+}
+
+/// The result of one poll. Resources that were listed but whose state couldn't be fetched keep
+/// their previous snapshot, rather than being reported as deleted and later created again.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Poll {
+    pub snapshots: Vec<Snapshot>,
+    /// the kind and ID of each resource whose state couldn't be fetched
+    pub failed: Vec<(ResourceKind, String)>
+}
+
+impl Poll {
+    /// the snapshots of this poll, with the previous snapshot standing in for each failed resource
+    pub fn resolve(self, prev: &[Snapshot]) -> Vec<Snapshot> {
+        let mut current = self.snapshots;
+        current.extend(prev.iter().filter(|p| self.failed.iter().any(|(kind, id)| *kind == p.kind && id == &p.id)).cloned());
+        current
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventType {
+    Created,
+    Deleted,
+    PhaseChanged,
+    HealthChanged,
+    EndpointPopulated
+}
+
+/// A single change between two polls, printed as one JSON line
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Event {
+    pub timestamp: DateTime<Utc>,
+    pub event: EventType,
+    pub kind: ResourceKind,
+    pub id: String,
+    pub name: String,
+    /// the field that changed, e.g. `endpoints.kibana`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    pub before: Value,
+    pub after: Value,
+    /// when the previous state was observed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous_timestamp: Option<DateTime<Utc>>
+}
+
+/// poll for resources every `interval`, writing an event for every change since the previous poll.
+/// The first poll only records a baseline. Runs until interrupted; failed polls are logged and skipped.
+pub fn stream<F, W>(interval: Duration, mut poll: F, mut out: W) -> Result<()>
+where F: FnMut() -> Result<Poll>,
+      W: Write
+{
+    let mut prev: Option<(DateTime<Utc>, Vec<Snapshot>)> = None;
+    loop {
+        match poll() {
+            Result::Ok(res) => {
+                let now = Utc::now();
+                let current = res.resolve(prev.as_ref().map(|(_, p)| p.as_slice()).unwrap_or_default());
+                if let Some((then, before)) = &prev {
+                    for event in diff(before, &current, *then, now) {
+                        writeln!(out, "{}", serde_json::to_string(&event)?)?;
+                    }
+                    out.flush()?;
+                }
+                prev = Some((now, current));
+            },
+            Err(e) => warn!("error polling for events: {:#}", e)
+        }
+        thread::sleep(interval);
+    }
+}
+
+/// compute the events between two sets of snapshots
+pub fn diff(prev: &[Snapshot], current: &[Snapshot], then: DateTime<Utc>, now: DateTime<Utc>) -> Vec<Event> {
+    let event = |event: EventType, snap: &Snapshot, field: Option<String>, before: Value, after: Value| Event {
+        timestamp: now,
+        event,
+        kind: snap.kind,
+        id: snap.id.clone(),
+        name: snap.name.clone(),
+        field,
+        before,
+        after,
+        previous_timestamp: Some(then)
+    };
+
+    let mut events = Vec::new();
+    for snap in current {
+        let Some(old) = prev.iter().find(|p| p.kind == snap.kind && p.id == snap.id) else {
+            events.push(event(EventType::Created, snap, None, Value::Null, describe(snap)));
+            continue;
+        };
+        if old.phase != snap.phase {
+            events.push(event(EventType::PhaseChanged, snap, Some("phase".to_string()), json!(old.phase), json!(snap.phase)));
+        }
+        if old.healthy != snap.healthy {
+            events.push(event(EventType::HealthChanged, snap, Some("healthy".to_string()), json!(old.healthy), json!(snap.healthy)));
+        }
+        for (name, url) in &snap.endpoints {
+            if old.endpoints.get(name) != Some(url) {
+                events.push(event(EventType::EndpointPopulated, snap, Some(format!("endpoints.{}", name)),
+                    json!(old.endpoints.get(name)), json!(url)));
+            }
+        }
+    }
+    for gone in prev.iter().filter(|p| !current.iter().any(|c| c.kind == p.kind && c.id == p.id)) {
+        events.push(event(EventType::Deleted, gone, None, describe(gone), Value::Null));
+    }
+    events
+}
+
+/// the full state of a snapshot, used as the before or after value of created and deleted events
+fn describe(snap: &Snapshot) -> Value {
+    json!({
+        "phase": snap.phase,
+        "healthy": snap.healthy,
+        "endpoints": snap.endpoints
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use chrono::Utc;
+    use serde_json::json;
+
+    use crate::state::ResourceKind;
+
+    use super::{Snapshot, EventType, Poll, diff};
+
+    fn project(id: &str, phase: &str, kibana: Option<&str>) -> Snapshot {
+        let mut endpoints = BTreeMap::new();
+        if let Some(url) = kibana {
+            endpoints.insert("kibana".to_string(), url.to_string());
+        }
+        Snapshot { kind: ResourceKind::Serverless, id: id.to_string(), name: format!("project-{}", id),
+            phase: Some(phase.to_string()), healthy: None, endpoints }
+    }
+
+    #[test]
+    fn test_diff() {
+        let now = Utc::now();
+        let prev = vec![project("a", "initializing", None), project("b", "initialized", Some("https://kb-b"))];
+        let current = vec![project("a", "initialized", Some("https://kb-a")), project("c", "initializing", None)];
+
+        let events = diff(&prev, &current, now, now);
+        let types: Vec<EventType> = events.iter().map(|e| e.event).collect();
+        assert_eq!(types, vec![EventType::PhaseChanged, EventType::EndpointPopulated, EventType::Created, EventType::Deleted]);
+        assert_eq!(events[0].before, json!("initializing"));
+        assert_eq!(events[0].after, json!("initialized"));
+        assert_eq!(events[1].field.as_deref(), Some("endpoints.kibana"));
+        assert_eq!(events[3].id, "b");
+
+        let line = serde_json::to_value(&events[0]).unwrap();
+        assert_eq!(line["event"], "phase_changed");
+        assert_eq!(line["kind"], "serverless");
+
+        assert!(diff(&current, &current, now, now).is_empty());
+    }
+
+    #[test]
+    fn test_poll_keeps_failed() {
+        let now = Utc::now();
+        let prev = vec![project("a", "initializing", None), project("b", "initialized", None)];
+        let poll = Poll {
+            snapshots: vec![project("a", "initialized", None)],
+            failed: vec![(ResourceKind::Serverless, "b".to_string()), (ResourceKind::Serverless, "new".to_string())]
+        };
+
+        let current = poll.resolve(&prev);
+        assert_eq!(current.len(), 2);
+        let types: Vec<EventType> = diff(&prev, &current, now, now).iter().map(|e| e.event).collect();
+        assert_eq!(types, vec![EventType::PhaseChanged]);
+    }
+}
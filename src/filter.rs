@@ -0,0 +1,236 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, NaiveDate, Utc};
+use regex::Regex;
+use serde::Serialize;
+
+use crate::clients::client::{ResultFormatting, TableFormatting, ListFormatting};
+
+/// Gives access to the named fields of a listed item, for filtering, sorting and column selection.
+/// Returns None if the item doesn't have the field.
+pub trait Fields {
+    /// every field name `field` answers to, used to reject filters, sorts and columns the type doesn't have
+    const NAMES: &'static [&'static str];
+
+    fn field(&self, name: &str) -> Option<String>;
+}
+
+/// A single `--filter` expression
+#[derive(Clone, Debug)]
+pub enum Predicate {
+    /// `field=value`
+    Equals{field: String, value: String},
+    /// `field~=regex`
+    Matches{field: String, re: Regex},
+    /// `created_after=<date or duration>`
+    CreatedAfter(DateTime<Utc>)
+}
+
+impl Predicate {
+    /// check if an item matches the predicate. Items missing the field never match.
+    pub fn matches<T: Fields>(&self, item: &T) -> bool {
+        match self {
+            Predicate::Equals { field, value } => item.field(field).is_some_and(|v| &v == value),
+            Predicate::Matches { field, re } => item.field(field).is_some_and(|v| re.is_match(&v)),
+            Predicate::CreatedAfter(after) => item.field("created_at")
+                .and_then(|v| parse_time(&v).ok())
+                .is_some_and(|created| &created > after)
+        }
+    }
+}
+
+impl Predicate {
+    /// the item field the predicate reads
+    fn field(&self) -> &str {
+        match self {
+            Predicate::Equals { field, .. } | Predicate::Matches { field, .. } => field,
+            Predicate::CreatedAfter(_) => "created_at"
+        }
+    }
+}
+
+/// parse a `--filter` argument
+pub fn parse_predicate(raw: &str) -> Result<Predicate> {
+    if let Some((field, re)) = raw.split_once("~=") {
+        let re = Regex::new(re).map_err(|e| anyhow!("invalid regex in filter '{}': {}", raw, e))?;
+        return Ok(Predicate::Matches { field: field.trim().to_string(), re })
+    }
+    let (field, value) = raw.split_once('=')
+        .ok_or_else(|| anyhow!("filter '{}' should be in the form field=value or field~=regex", raw))?;
+    let field = field.trim();
+    if field == "created_after" {
+        return Ok(Predicate::CreatedAfter(parse_after(value.trim())?))
+    }
+    Ok(Predicate::Equals { field: field.to_string(), value: value.to_string() })
+}
+
+/// parse the value of a created_after filter, either a timestamp, a date, or a duration relative to now
+fn parse_after(value: &str) -> Result<DateTime<Utc>> {
+    if let Result::Ok(ts) = parse_time(value) {
+        return Ok(ts)
+    }
+    if let Result::Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc())
+    }
+    let ago = humantime::parse_duration(value)
+        .map_err(|_| anyhow!("created_after value '{}' should be a RFC 3339 timestamp, a YYYY-MM-DD date, or a duration like 2d", value))?;
+    Ok(Utc::now() - chrono::Duration::from_std(ago)?)
+}
+
+fn parse_time(value: &str) -> Result<DateTime<Utc>> {
+    Ok(DateTime::parse_from_rfc3339(value)?.with_timezone(&Utc))
+}
+
+/// fail if a filter, the sort key or a column refers to a field that items of type T don't have,
+/// rather than silently matching nothing
+pub fn check<T: Fields>(filters: &[Predicate], sort: Option<&str>, columns: Option<&[String]>) -> Result<()> {
+    let used = filters.iter().map(|f| (f.field(), "filter"))
+        .chain(sort.map(|s| (s, "sort")))
+        .chain(columns.unwrap_or_default().iter().map(|c| (c.as_str(), "column")));
+    for (name, usage) in used {
+        if !T::NAMES.contains(&name) {
+            return Err(anyhow!("{} on unknown field '{}' for this resource type, available fields are: {}", usage, name, T::NAMES.join(", ")))
+        }
+    }
+    Ok(())
+}
+
+/// filter, sort and truncate a list of items
+pub fn apply<T: Fields>(items: Vec<T>, filters: &[Predicate], sort: Option<&str>, limit: Option<usize>) -> Vec<T> {
+    let mut items: Vec<T> = items.into_iter().filter(|i| filters.iter().all(|f| f.matches(i))).collect();
+    if let Some(key) = sort {
+        // items missing the sort field go last
+        items.sort_by_cached_key(|i| {
+            let val = i.field(key);
+            (val.is_none(), val)
+        });
+    }
+    if let Some(max) = limit {
+        items.truncate(max);
+    }
+    items
+}
+
+/// A list of items reduced to a user-selected set of columns
+#[derive(Debug)]
+pub struct ColumnView {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Option<String>>>
+}
+
+impl ColumnView {
+    pub fn new<T: Fields>(items: &[T], columns: &[String]) -> Self {
+        let rows = items.iter().map(|i| columns.iter().map(|c| i.field(c)).collect()).collect();
+        ColumnView { columns: columns.to_vec(), rows }
+    }
+}
+
+impl Serialize for ColumnView {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let rows: Vec<BTreeMap<&str, &Option<String>>> = self.rows.iter()
+            .map(|row| self.columns.iter().map(String::as_str).zip(row.iter()).collect())
+            .collect();
+        rows.serialize(serializer)
+    }
+}
+
+impl ResultFormatting for ColumnView {
+    fn compact(&self) -> String {
+        let mut acc = String::new();
+        for row in &self.rows {
+            let vals: Vec<&str> = row.iter().map(|v| v.as_deref().unwrap_or("")).collect();
+            acc = format!("{}{}\n", acc, vals.join(", "));
+        }
+        acc
+    }
+}
+
+impl ListFormatting for ColumnView {
+    fn list_rows(&self) -> Result<Vec<serde_json::Value>> {
+        match serde_json::to_value(self)? {
+            serde_json::Value::Array(rows) => Ok(rows),
+            other => Ok(vec![other])
+        }
+    }
+}
+
+impl TableFormatting for ColumnView {
+    fn headers(&self) -> Vec<String> {
+        self.columns.clone()
+    }
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.rows.iter().map(|row| row.iter().map(|v| v.clone().unwrap_or_default()).collect()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Fields, parse_predicate, apply, check, Predicate};
+
+    struct Item {
+        name: String,
+        region: String,
+        created_at: Option<String>
+    }
+
+    impl Fields for Item {
+        const NAMES: &'static [&'static str] = &["name", "region", "created_at"];
+
+        fn field(&self, name: &str) -> Option<String> {
+            match name {
+                "name" => Some(self.name.clone()),
+                "region" => Some(self.region.clone()),
+                "created_at" => self.created_at.clone(),
+                _ => None
+            }
+        }
+    }
+
+    fn items() -> Vec<Item> {
+        vec![
+            Item{name: "test-b".to_string(), region: "aws-eu-west-1".to_string(), created_at: Some("2023-08-02T00:00:00Z".to_string())},
+            Item{name: "other".to_string(), region: "aws-us-east-1".to_string(), created_at: None},
+            Item{name: "test-a".to_string(), region: "aws-eu-west-1".to_string(), created_at: Some("2023-07-01T00:00:00Z".to_string())},
+        ]
+    }
+
+    #[test]
+    fn test_parse_predicate() {
+        assert!(matches!(parse_predicate("name~=^test-").unwrap(), Predicate::Matches{field, ..} if field == "name"));
+        assert!(matches!(parse_predicate("region=aws-eu-west-1").unwrap(), Predicate::Equals{field, value} if field == "region" && value == "aws-eu-west-1"));
+        assert!(matches!(parse_predicate("created_after=2023-08-01").unwrap(), Predicate::CreatedAfter(_)));
+        assert!(matches!(parse_predicate("created_after=2d").unwrap(), Predicate::CreatedAfter(_)));
+        assert!(parse_predicate("name").is_err());
+        assert!(parse_predicate("name~=(").is_err());
+        assert!(parse_predicate("created_after=yesterday-ish").is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        let filters = vec![parse_predicate("name~=^test-").unwrap()];
+        let res = apply(items(), &filters, Some("name"), None);
+        let names: Vec<&str> = res.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(names, vec!["test-a", "test-b"]);
+
+        let filters = vec![parse_predicate("created_after=2023-08-01").unwrap()];
+        let res = apply(items(), &filters, None, None);
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].name, "test-b");
+
+        let res = apply(items(), &[], Some("created_at"), Some(2));
+        let names: Vec<&str> = res.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(names, vec!["test-a", "test-b"]);
+    }
+
+    #[test]
+    fn test_check() {
+        let filters = vec![parse_predicate("name~=^test-").unwrap(), parse_predicate("created_after=2d").unwrap()];
+        assert!(check::<Item>(&filters, Some("region"), Some(&["name".to_string()])).is_ok());
+
+        let err = check::<Item>(&[parse_predicate("created_by=me").unwrap()], None, None).unwrap_err();
+        assert!(err.to_string().contains("'created_by'"), "{}", err);
+        assert!(check::<Item>(&[], Some("id"), None).is_err());
+        assert!(check::<Item>(&[], None, Some(&["cloud_id".to_string()])).is_err());
+    }
+}
@@ -0,0 +1,209 @@
+use std::time::Duration;
+
+use anyhow::{Result, Context, anyhow};
+use log::info;
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use url::form_urlencoded;
+
+use crate::{clients::{client::{ESSClient, ResultFormatting, TableFormatting, ListFormatting, headers}, serverless::ProjectOverrides}, dataplane::DataPlaneTarget, ready, redact};
+
+/// Everything an elastic-agent needs to enroll with a project's Fleet server
+#[derive(Serialize, Debug, Clone)]
+pub struct FleetBootstrap {
+    pub policy_id: String,
+    pub policy_name: String,
+    pub fleet_url: String,
+    pub enrollment_token: String,
+    /// the Fleet docker image override the project was created with, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fleet_image: Option<String>
+}
+
+impl FleetBootstrap {
+    /// shell `export` lines, for use with `eval`
+    pub fn exports(&self) -> String {
+        format!("export FLEET_URL='{}'\nexport FLEET_ENROLLMENT_TOKEN='{}'", self.fleet_url, self.enrollment_token)
+    }
+}
+
+impl ResultFormatting for FleetBootstrap {
+    fn compact(&self) -> String {
+        format!("{}, {}", self.fleet_url, self.enrollment_token)
+    }
+}
+
+impl ListFormatting for FleetBootstrap {}
+
+impl TableFormatting for FleetBootstrap {
+    fn headers(&self) -> Vec<String> {
+        headers(&["POLICY ID", "POLICY NAME", "FLEET URL", "ENROLLMENT TOKEN", "FLEET IMAGE"])
+    }
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![self.policy_id.clone(), self.policy_name.clone(), self.fleet_url.clone(), self.enrollment_token.clone(),
+            self.fleet_image.clone().unwrap_or_default()]]
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct FleetSetupStatus {
+    #[serde(rename = "isReady")]
+    is_ready: bool
+}
+
+#[derive(Deserialize, Debug)]
+struct ItemList<T> {
+    items: Vec<T>
+}
+
+#[derive(Deserialize, Debug)]
+struct Item<T> {
+    item: T
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct AgentPolicy {
+    id: String,
+    name: String
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct EnrollmentKey {
+    api_key: String,
+    policy_id: Option<String>,
+    #[serde(default)]
+    active: bool
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct FleetServerHost {
+    #[serde(default)]
+    host_urls: Vec<String>,
+    #[serde(default)]
+    is_default: bool
+}
+
+/// wait for Fleet to be ready in the project's Kibana, find or create the named agent policy,
+/// and fetch an enrollment token for it along with the Fleet server URL
+pub fn bootstrap(client: &ESSClient, kibana: &DataPlaneTarget, policy_name: &str, fleet_image: Option<String>, timeout: Duration) -> Result<FleetBootstrap> {
+    wait_for_fleet(client, kibana, timeout)?;
+    let policy = find_or_create_policy(client, kibana, policy_name)?;
+    let enrollment_token = enrollment_token(client, kibana, &policy.id)?;
+    redact::register(&enrollment_token);
+    let fleet_url = fleet_server_url(client, kibana)?;
+    Ok(FleetBootstrap { policy_id: policy.id, policy_name: policy.name, fleet_url, enrollment_token, fleet_image })
+}
+
+/// the Fleet docker image override a project was created with (`sl create --fleet-docker-override`).
+/// If an image is expected, fail unless the project runs exactly that image, so agents under test
+/// never enroll against the wrong Fleet server build.
+pub fn fleet_image(id: &str, overrides: Option<&ProjectOverrides>, expected: Option<&str>) -> Result<Option<String>> {
+    let image = overrides.and_then(|o| o.fleet.as_ref()).map(|f| f.docker_image.clone());
+    match (expected, image.as_deref()) {
+        (Some(want), Some(got)) if want != got => Err(anyhow!("project {} runs Fleet image {}, not {}", id, got, want)),
+        (Some(want), None) => Err(anyhow!("project {} has no Fleet docker override, expected {}", id, want)),
+        _ => {
+            if let Some(got) = &image {
+                info!("project {} runs Fleet image {}", id, got);
+            }
+            Ok(image)
+        }
+    }
+}
+
+/// quote a value for use in a KQL query
+fn kql_quote(val: &str) -> String {
+    format!("\"{}\"", val.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// poll until Fleet reports it's ready, requesting setup if it hasn't been done yet.
+/// Errors are retried, as Kibana is often unavailable right after a project is created.
+fn wait_for_fleet(client: &ESSClient, kibana: &DataPlaneTarget, timeout: Duration) -> Result<()> {
+    let mut setup_requested = false;
+    ready::probe("Fleet", timeout, || {
+        let status: FleetSetupStatus = kibana.call(client, Method::GET, "api/fleet/agents/setup", None)?;
+        if !status.is_ready && !setup_requested {
+            info!("Fleet is not set up yet, requesting setup");
+            kibana.call::<serde_json::Value>(client, Method::POST, "api/fleet/setup", None).context("error setting up Fleet")?;
+            setup_requested = true;
+        }
+        Ok(status.is_ready)
+    })
+}
+
+fn find_or_create_policy(client: &ESSClient, kibana: &DataPlaneTarget, name: &str) -> Result<AgentPolicy> {
+    let kuery = format!("ingest-agent-policies.name:{}", kql_quote(name));
+    let path = format!("api/fleet/agent_policies?perPage=1000&kuery={}", form_urlencoded::byte_serialize(kuery.as_bytes()).collect::<String>());
+    let existing: ItemList<AgentPolicy> = kibana.call(client, Method::GET, &path, None).context("error listing agent policies")?;
+    if let Some(policy) = existing.items.into_iter().find(|p| p.name == name) {
+        info!("using existing agent policy {} ({})", policy.name, policy.id);
+        return Ok(policy)
+    }
+    let body = json!({"name": name, "namespace": "default", "description": "created by esscli fleet-bootstrap"});
+    let created: Item<AgentPolicy> = kibana.call(client, Method::POST, "api/fleet/agent_policies", Some(body))
+        .context("error creating agent policy")?;
+    info!("created agent policy {} ({})", created.item.name, created.item.id);
+    Ok(created.item)
+}
+
+fn enrollment_token(client: &ESSClient, kibana: &DataPlaneTarget, policy_id: &str) -> Result<String> {
+    let kuery = format!("policy_id:{}", kql_quote(policy_id));
+    let path = format!("api/fleet/enrollment_api_keys?perPage=1000&kuery={}", form_urlencoded::byte_serialize(kuery.as_bytes()).collect::<String>());
+    let keys: ItemList<EnrollmentKey> = kibana.call(client, Method::GET, &path, None).context("error listing enrollment tokens")?;
+    if let Some(key) = keys.items.into_iter().find(|k| k.active && k.policy_id.as_deref() == Some(policy_id)) {
+        return Ok(key.api_key)
+    }
+    let created: Item<EnrollmentKey> = kibana.call(client, Method::POST, "api/fleet/enrollment_api_keys", Some(json!({"policy_id": policy_id})))
+        .context("error creating enrollment token")?;
+    Ok(created.item.api_key)
+}
+
+/// the URL agents should use to reach the project's Fleet server
+pub fn fleet_server_url(client: &ESSClient, kibana: &DataPlaneTarget) -> Result<String> {
+    let hosts: ItemList<FleetServerHost> = kibana.call(client, Method::GET, "api/fleet/fleet_server_hosts", None)
+        .context("error listing Fleet server hosts")?;
+    pick_fleet_url(&hosts.items).ok_or_else(|| anyhow!("no Fleet server hosts are configured"))
+}
+
+/// the first URL of the default Fleet server host, or of any host if none is the default
+fn pick_fleet_url(hosts: &[FleetServerHost]) -> Option<String> {
+    hosts.iter().find(|h| h.is_default && !h.host_urls.is_empty())
+        .or_else(|| hosts.iter().find(|h| !h.host_urls.is_empty()))
+        .map(|h| h.host_urls[0].clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clients::serverless::{ApplicationOverride, ProjectOverrides};
+
+    use super::{FleetServerHost, ItemList, fleet_image, kql_quote, pick_fleet_url};
+
+    #[test]
+    fn test_pick_fleet_url() {
+        let raw = r#"{"items": [
+            {"id": "other", "host_urls": ["https://other:443"], "is_default": false},
+            {"id": "default", "host_urls": ["https://fleet.example.com:443"], "is_default": true}
+        ]}"#;
+        let hosts: ItemList<FleetServerHost> = serde_json::from_str(raw).unwrap();
+        assert_eq!(pick_fleet_url(&hosts.items).as_deref(), Some("https://fleet.example.com:443"));
+        assert_eq!(pick_fleet_url(&hosts.items[..1]).as_deref(), Some("https://other:443"));
+        assert_eq!(pick_fleet_url(&[]), None);
+    }
+
+    #[test]
+    fn test_fleet_image() {
+        let overrides = ProjectOverrides { fleet: Some(ApplicationOverride { docker_image: "fleet:8.15-SNAPSHOT".to_string() }), ..Default::default() };
+        assert_eq!(fleet_image("abc", Some(&overrides), None).unwrap().as_deref(), Some("fleet:8.15-SNAPSHOT"));
+        assert!(fleet_image("abc", Some(&overrides), Some("fleet:8.15-SNAPSHOT")).is_ok());
+        assert!(fleet_image("abc", Some(&overrides), Some("fleet:other")).is_err());
+        assert!(fleet_image("abc", None, Some("fleet:8.15-SNAPSHOT")).is_err());
+        assert_eq!(fleet_image("abc", None, None).unwrap(), None);
+    }
+
+    #[test]
+    fn test_kql_quote() {
+        assert_eq!(kql_quote("esscli"), r#""esscli""#);
+        assert_eq!(kql_quote(r#"my "quoted" \ policy"#), r#""my \"quoted\" \\ policy""#);
+    }
+}
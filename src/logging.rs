@@ -0,0 +1,151 @@
+use std::{fs::OpenOptions, io::Write, sync::Mutex};
+
+use anyhow::{Result, Context};
+use chrono::Utc;
+use log::{LevelFilter, Log, Metadata, Record};
+use serde_json::json;
+
+use crate::{cli::LogFormat, redact};
+
+/// log lines from other crates are capped at this level, so trace only adds our own wire dumps
+const DEPENDENCY_MAX_LEVEL: LevelFilter = LevelFilter::Debug;
+
+/// Writes log lines as text or JSON to stderr or a file, masking any registered secrets
+pub struct Logger {
+    level: LevelFilter,
+    format: LogFormat,
+    out: Mutex<Box<dyn Write + Send>>
+}
+
+/// install the logger for the rest of the process
+pub fn init(level: LevelFilter, format: LogFormat, file: Option<&str>) -> Result<()> {
+    let out: Box<dyn Write + Send> = match file {
+        Some(path) => {
+            let expanded = shellexpand::tilde(path).to_string();
+            Box::new(OpenOptions::new().create(true).append(true).open(&expanded)
+                .with_context(|| format!("error opening log file {}", expanded))?)
+        },
+        None => Box::new(std::io::stderr())
+    };
+    log::set_boxed_logger(Box::new(Logger { level, format, out: Mutex::new(out) })).context("error creating logger")?;
+    log::set_max_level(level);
+    Ok(())
+}
+
+impl Logger {
+    /// format a record as a single line
+    fn format(&self, record: &Record) -> String {
+        let timestamp = Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+        let message = redact::redact(&record.args().to_string());
+        match self.format {
+            LogFormat::Text => format!("{} {:<5} [{}] {}", timestamp, record.level(), record.target(), message),
+            LogFormat::Json => json!({
+                "timestamp": timestamp,
+                "level": record.level().as_str(),
+                "target": record.target(),
+                "message": message
+            }).to_string()
+        }
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        let max = if metadata.target().starts_with(env!("CARGO_PKG_NAME")) {
+            self.level
+        } else {
+            self.level.min(DEPENDENCY_MAX_LEVEL)
+        };
+        metadata.level() <= max
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return
+        }
+        let line = self.format(record);
+        let mut out = self.out.lock().unwrap_or_else(|e| e.into_inner());
+        // there's nowhere left to report a failure to write a log line
+        let _ = writeln!(out, "{}", line);
+    }
+
+    fn flush(&self) {
+        let _ = self.out.lock().unwrap_or_else(|e| e.into_inner()).flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, sync::{Arc, Mutex}};
+
+    use log::{Level, LevelFilter, Log, Record};
+    use serde_json::Value;
+
+    use crate::{cli::LogFormat, redact};
+
+    use super::Logger;
+
+    fn logger(format: LogFormat) -> Logger {
+        Logger { level: LevelFilter::Info, format, out: Mutex::new(Box::new(std::io::sink())) }
+    }
+
+    /// collects written log lines so a test can read them back
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_format() {
+        let format = |fmt| logger(fmt).format(&Record::builder()
+            .level(Level::Info)
+            .target("esscli::main")
+            .args(format_args!("removed {}", "abc"))
+            .build());
+
+        let text = format(LogFormat::Text);
+        assert!(text.ends_with("INFO  [esscli::main] removed abc"), "{}", text);
+
+        let line: Value = serde_json::from_str(&format(LogFormat::Json)).unwrap();
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["message"], "removed abc");
+    }
+
+    #[test]
+    fn test_enabled() {
+        let mut log = logger(LogFormat::Text);
+        log.level = LevelFilter::Trace;
+        let ours = Record::builder().level(Level::Trace).target("esscli::clients::client").build();
+        let theirs = Record::builder().level(Level::Trace).target("hyper::proto").build();
+        assert!(log.enabled(ours.metadata()));
+        assert!(!log.enabled(theirs.metadata()));
+    }
+
+    #[test]
+    fn test_trace_response_redacted() {
+        let capture = Capture::default();
+        let log = Logger { level: LevelFilter::Trace, format: LogFormat::Text, out: Mutex::new(Box::new(capture.clone())) };
+        let body = br#"{"id": "abc", "username": "admin", "password": "reset-pass-9876"}"#;
+
+        // as ESSClient::execute does before tracing a response
+        redact::register_body(body);
+        log.log(&Record::builder()
+            .level(Level::Trace)
+            .target("esscli::clients::client")
+            .args(format_args!("response: 200 OK POST https://api\n{}", String::from_utf8_lossy(body)))
+            .build());
+
+        let written = String::from_utf8(capture.0.lock().unwrap().clone()).unwrap();
+        assert!(written.contains("\"username\": \"admin\""), "{}", written);
+        assert!(!written.contains("reset-pass-9876"), "{}", written);
+    }
+}
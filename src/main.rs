@@ -1,26 +1,74 @@
-use cli::{Cli, ServerlessCmd, StatefulCmd, RawReq};
+use std::io::IsTerminal;
+
+use cli::{Cli, ServerlessCmd, StatefulCmd, ApiKeyCmd, OutputType, PoolCmd, RawReq};
 use clap::Parser;
-use clients::{client::{ResultFormatting, ESSClient}, serverless::{CreateProject, ProjectOverrides, ApplicationOverride}, create_deployment::DeploymentCreateRequest};
-use config::{get_config, DeploymentSpecificConfig};
+use clients::{client::{ESSClient, HttpStatusError, Preview, RawResponse, ResultFormatting, is_dry_run, is_not_found}, serverless::{CreateProject, UpdateProject, ProjectOverrides, ApplicationOverride, ServerlessClient, Project, ProjectsList, PROJECT_TYPES}, create_deployment::DeploymentCreateRequest, deployments::{DeploymentsList, DeploymentsListingData}};
+use config::{get_config, DeploymentSpecificConfig, RecipeTarget};
+use credentials::{CredentialStore, StoredCredentials};
+use dataplane::Service;
+use events::{Poll, Snapshot};
+use filter::ColumnView;
+use manifest::{Action, ApplyReport, ApplyResult, Op};
+use output::{print_generic_struct, print_response};
+use state::{Inventory, InventoryEntry, ResourceKind, ResourceStatus, ResourceStatusList};
 use anyhow::{Result, Ok, anyhow, Context};
-use serde::Serialize;
+use chrono::Utc;
+use log::{info, warn, error};
+use ttl::deployment_expiry;
 use url::Url;
+use watch::WatchItem;
 
+mod apikey;
 mod cli;
 mod clients;
 mod config;
+mod credentials;
+mod dataplane;
+mod events;
+mod filter;
+mod fleet;
+mod logging;
+mod manifest;
+mod output;
+mod pool;
+mod query;
+mod ready;
+mod recipe;
+mod redact;
+mod seed;
+mod template;
+mod watch;
+mod state;
+mod ttl;
+
+fn main() -> Result<()> {
+    match run() {
+        // the request was printed instead of being sent
+        Err(e) if is_dry_run(&e) => Ok(()),
+        res => res
+    }
+}
 
-fn main() -> Result<()>{
-    let cli = Cli::parse();
+fn run() -> Result<()>{
+    let mut cli = Cli::parse();
     let cfg = get_config()?;
-    let client = clients::client::ESSClient::new(cfg.clone()).context("error reading config file")?;
+    if let Some(format) = &cli.output.format {
+        cli.output.template = Some(template::parse(cfg.resolve_format(format)?)?);
+    }
+    let mut client = clients::client::ESSClient::new(cfg.clone()).context("error reading config file")?;
+    client.set_preview(match (cli.print_curl, cli.dry_run) {
+        (true, _) => Some(Preview::Curl),
+        (false, true) => Some(Preview::Request),
+        (false, false) => None
+    });
 
-    let lvl = match cli.verbose {
-        false => log::Level::Info ,
-        true => log::Level::Debug
-        
+    let lvl = match (cli.log.log_level, cli.verbose) {
+        (Some(level), _) => level.filter(),
+        (None, false) => log::LevelFilter::Info,
+        (None, true) => log::LevelFilter::Debug
     };
-    simple_logger::init_with_level(lvl).context("error creating logger")?;
+    redact::set_show_secrets(cli.output.show_secrets);
+    logging::init(lvl, cli.log.log_format, cli.log.log_file.as_deref())?;
    
     match &cli.command{
         cli::Types::Setup{path} => {
@@ -28,69 +76,173 @@ fn main() -> Result<()>{
         },
         cli::Types::SF(cmd) => {
             match cmd{
-                StatefulCmd::List => {
-                    let res = client.stateful()?.list().context("error listing projects")?;
-                    print_generic_struct(cli.out, &res)?;
+                StatefulCmd::List(opts) => {
+                    filter::check::<DeploymentsListingData>(&opts.filter, opts.sort.map(|s| s.field()), opts.columns.as_deref())?;
+                    let list = || -> Result<DeploymentsList> {
+                        let mut res = client.stateful()?.list().context("error listing projects")?;
+                        res.deployments = filter::apply(res.deployments, &opts.filter, opts.sort.map(|s| s.field()), opts.limit);
+                        Ok(res)
+                    };
+                    if let Some(interval) = opts.watch.watch {
+                        let sf = client.stateful()?;
+                        return watch::watch(interval.into(), || {
+                            // the listing has no health, so each deployment is fetched
+                            Ok(list()?.deployments.iter().map(|d| {
+                                let state = match sf.get(&d.id) {
+                                    Result::Ok(dep) if dep.healthy => "healthy".to_string(),
+                                    Result::Ok(_) => "unhealthy".to_string(),
+                                    Err(e) => format!("error: {}", e)
+                                };
+                                WatchItem { id: d.id.clone(), label: d.compact(), state }
+                            }).collect())
+                        })
+                    }
+                    let res = list()?;
+                    match &opts.columns {
+                        Some(columns) => print_generic_struct(&cli.output, &ColumnView::new(&res.deployments, columns))?,
+                        None => print_generic_struct(&cli.output, &res)?
+                    }
                 }, 
-                StatefulCmd::Get{id} => {
-                    let res = client.stateful()?.get(id).context("error fetching project")?;
-                    print_generic_struct(cli.out, &res)?;
+                StatefulCmd::Get{ids, watch} => {
+                    let ids = ids.resolve()?;
+                    if let Some(interval) = watch.watch {
+                        return watch::watch(interval.into(), || {
+                            ids.iter().map(|id| {
+                                let dep = client.stateful()?.get(id).context("error fetching project")?;
+                                let state = if dep.healthy { "healthy" } else { "unhealthy" };
+                                Ok(WatchItem { id: dep.id.clone(), label: format!("{} ({})", dep.name, dep.id), state: state.to_string() })
+                            }).collect()
+                        })
+                    }
+                    for id in ids {
+                        let res = client.stateful()?.get(&id).context("error fetching project")?;
+                        print_generic_struct(&cli.output, &res)?;
+                    }
                 },
                 StatefulCmd::Raw(raw) => {
                     let parsed = cfg.resolve_stateful();
-                    let res = handle_raw_request(parsed, raw, client).context("error performing raw HTTP request")?;
-                    println!("{}", res);
+                    let res = handle_raw_request(parsed, raw, &client).context("error performing raw HTTP request")?;
+                    print_response(&cli.output, &res, raw.include)?;
+                    check_status(&res)?;
                 },
-                StatefulCmd::Shutdown { ids } => {
-                    for id in ids {
-                        let res = client.stateful()?.shutdown(id).context("error shutting down")?;
-                        print_generic_struct(cli.out, &res)?;
+                StatefulCmd::Shutdown(ids) => {
+                    for id in ids.resolve()? {
+                        let res = match client.stateful()?.shutdown(&id) {
+                            // keep previewing the remaining IDs
+                            Err(e) if is_dry_run(&e) => continue,
+                            res => res.context("error shutting down")?
+                        };
+                        print_generic_struct(&cli.output, &res)?;
+                        forget_resource(&id);
                     }
 
                 },
-                StatefulCmd::Create { name, region, version } => {
-                    let req = create_deployment_request_from_cli(region.clone(), 
+                StatefulCmd::Creds { id } => {
+                    let store = CredentialStore::open_default()?;
+                    let creds = store.get(id).ok_or_else(|| anyhow!("no stored credentials for deployment {}", id))?;
+                    print_generic_struct(&cli.output, creds)?;
+                },
+                StatefulCmd::Create { name, region, version, ttl } => {
+                    let mut req = create_deployment_request_from_cli(region.clone(), 
                     name.clone(), 
                     version.clone(), 
-                    cfg.config.default_deployment).context("error creating deployment request")?;
+                    cfg.config.default_deployment.clone()).context("error creating deployment request")?;
+                    let expires = ttl.map(|t| ttl::expiry_from(None, t.into())).transpose()?;
+                    if let Some(exp) = expires {
+                        ttl::tag_deployment_request(&mut req, exp);
+                    }
                     let resp = client.stateful()?.create(req).context("error creating cluster")?;
-                    println!("{:#?}", resp);
+                    print_generic_struct(&cli.output, &resp)?;
+                    let created_region = region.clone().or_else(|| resp.resources.iter().find_map(|r| r.region.clone()));
+                    let mut entry = InventoryEntry::new(&resp.id, ResourceKind::Stateful, &resp.name, created_region, &cfg.resolve_stateful().url);
+                    entry.expires_at = expires;
+                    record_resource(entry);
+                    // only the elasticsearch resource carries credentials, and only on create
+                    if let Some(creds) = resp.resources.iter().find_map(|r| r.credentials.clone()) {
+                        save_credentials(&resp.id, StoredCredentials::Stateful(creds));
+                    }
                 }
             }
 
         },
         cli::Types::SL(cmd) => {
             match cmd {
-                ServerlessCmd::List => {
-                    let res = client.serverless()?.list().context("error listing projects")?;
-                    print_generic_struct(cli.out, &res)?;
+                ServerlessCmd::List(opts) => {
+                    filter::check::<Project>(&opts.filter, opts.sort.map(|s| s.field()), opts.columns.as_deref())?;
+                    let list = || -> Result<ProjectsList> {
+                        let mut res = client.serverless()?.list().context("error listing projects")?;
+                        res.items = filter::apply(res.items, &opts.filter, opts.sort.map(|s| s.field()), opts.limit);
+                        Ok(res)
+                    };
+                    if let Some(interval) = opts.watch.watch {
+                        let sl = client.serverless()?;
+                        return watch::watch(interval.into(), || {
+                            // the listing has no phase, so each project's status is fetched
+                            Ok(list()?.items.iter().map(|p| {
+                                let state = match sl.status(&p.id) {
+                                    Result::Ok(status) => status.phase,
+                                    Err(e) => format!("error: {}", e)
+                                };
+                                WatchItem { id: p.id.clone(), label: p.compact(), state }
+                            }).collect())
+                        })
+                    }
+                    let res = list()?;
+                    match &opts.columns {
+                        Some(columns) => print_generic_struct(&cli.output, &ColumnView::new(&res.items, columns))?,
+                        None => print_generic_struct(&cli.output, &res)?
+                    }
                 },
-                ServerlessCmd::Get{id} => {
-                    let res = client.serverless()?.get(id).context("error fetching project")?;
-                    print_generic_struct(cli.out, &res)?;
+                ServerlessCmd::Get(ids) => {
+                    for id in ids.resolve()? {
+                        let res = client.serverless()?.get(&id).context("error fetching project")?;
+                        print_generic_struct(&cli.output, &res)?;
+                    }
                 },
                 ServerlessCmd::ResetCreds { id } => {
                     let res = client.serverless()?.reset_credentials(id).context("error resetting credentials")?;
-                    print_generic_struct(cli.out, &res)?;
+                    print_generic_struct(&cli.output, &res)?;
+                    save_credentials(id, StoredCredentials::Serverless(res));
                 },
-                ServerlessCmd::Status { id } => {
-                    let res = client.serverless()?.status(id).context("error fetching status")?;
-                    print_generic_struct(cli.out, &res)?;
+                ServerlessCmd::Creds { id } => {
+                    let store = CredentialStore::open_default()?;
+                    let creds = store.get(id).ok_or_else(|| anyhow!("no stored credentials for project {}, run reset-creds to create them", id))?;
+                    print_generic_struct(&cli.output, creds)?;
                 },
-                ServerlessCmd::Delete { ids } => {
+                ServerlessCmd::Status{ids, watch} => {
+                    let ids = ids.resolve()?;
+                    if let Some(interval) = watch.watch {
+                        return watch::watch(interval.into(), || {
+                            ids.iter().map(|id| {
+                                let res = client.serverless()?.status(id).context("error fetching status")?;
+                                Ok(WatchItem { id: id.clone(), label: id.clone(), state: res.phase })
+                            }).collect()
+                        })
+                    }
                     for id in ids {
-                        client.serverless()?.delete(id).context("error performing delete request")?;
+                        let res = client.serverless()?.status(&id).context("error fetching status")?;
+                        print_generic_struct(&cli.output, &res)?;
+                    }
+                },
+                ServerlessCmd::Delete(ids) => {
+                    for id in ids.resolve()? {
+                        match client.serverless()?.delete(&id) {
+                            // keep previewing the remaining IDs
+                            Err(e) if is_dry_run(&e) => continue,
+                            res => res.context("error performing delete request")?
+                        };
+                        forget_resource(&id);
                     }
                 },
                 ServerlessCmd::Create { name, region, wait, reset_creds, es_docker_override, 
-                    kibana_docker_override, fleet_docker_override } => {
+                    kibana_docker_override, fleet_docker_override, ttl, wait_ready, wait_fleet, ready_timeout } => {
                     // check region values
                     let serverless_cfg = cfg.resolve_serverless();
                     if serverless_cfg.region.is_none() && region.is_none() {
                         return Err(anyhow!("region value must be set in CLI or config"))
                     }
                     // default to the config value, then let cli override
-                    let mut region_final = serverless_cfg.region.unwrap_or_default();
+                    let mut region_final = serverless_cfg.region.clone().unwrap_or_default();
                     if let Some(reg) = region {
                         region_final = reg.to_string();
                     }
@@ -109,30 +261,270 @@ fn main() -> Result<()>{
                         }
                         req.overrides = Some(overrides);
                     }
-                    let res = client.serverless()?.create(req, *wait).context("error creating project")?;
-                    print_generic_struct(cli.out, &res)?;
+                    // checked before creating, so a ttl that's too long can't fail the command once the project exists
+                    let expires = ttl.map(|t| ttl::expiry_from(None, t.into())).transpose()?;
+                    let res = client.serverless()?.create(req, *wait || *wait_ready).context("error creating project")?;
+                    print_generic_struct(&cli.output, &res)?;
+                    let mut entry = InventoryEntry::new(&res.id, ResourceKind::Serverless, &res.name, Some(res.region_id.clone()), &serverless_cfg.url);
+                    entry.project_type = Some(res.project_type.clone());
+                    entry.expires_at = ttl.and_then(|t| ttl::expiry_from(Some(&res.metadata.created_at), t.into()).ok()).or(expires);
+                    record_resource(entry);
                     if *reset_creds{
-                        let res = client.serverless()?.reset_credentials(&res.id).context("error resetting credentials")?;
-                        print_generic_struct(cli.out, &res)?;
+                        let creds = client.serverless()?.reset_credentials(&res.id).context("error resetting credentials")?;
+                        print_generic_struct(&cli.output, &creds)?;
+                        save_credentials(&res.id, StoredCredentials::Serverless(creds));
+                    }
+                    if *wait_ready {
+                        ready::wait_ready(&client, &res.id, (*ready_timeout).into(), *wait_fleet)?;
                     }
                 },
                 ServerlessCmd::Regions => {
                     let regions = client.serverless()?.regions()?;
-                    // some hackery needed, since we get a list from the server, not an object
-                   if cli.out == cli::OutputType::Json {
-                    let formatted = serde_json::to_string_pretty(&regions)?;
-                    println!("{}", formatted);
-                   } else {
-                    for region in regions {
-                        print_generic_struct(cli.out, &region)?;
+                    print_generic_struct(&cli.output, &regions)?;
+                },
+                ServerlessCmd::Apikey(cmd) => match cmd {
+                    ApiKeyCmd::Create { id, name, role_descriptors, expiration } => {
+                        let roles = role_descriptors.as_deref()
+                            .map(|r| -> Result<serde_json::Value> {
+                                serde_json::from_slice(&cli::read_data(r)?).context("role descriptors should be a JSON object")
+                            })
+                            .transpose()?;
+                        let es = dataplane::resolve(&client, id, Service::Elasticsearch)?;
+                        let res = apikey::create(&client, &es, name, roles, expiration.as_deref())?;
+                        print_generic_struct(&cli.output, &res)?;
+                    },
+                    ApiKeyCmd::List { id, name } => {
+                        let es = dataplane::resolve(&client, id, Service::Elasticsearch)?;
+                        print_generic_struct(&cli.output, &apikey::list(&client, &es, name.as_deref())?)?;
+                    },
+                    ApiKeyCmd::Invalidate { id, key_ids } => {
+                        let es = dataplane::resolve(&client, id, Service::Elasticsearch)?;
+                        print_generic_struct(&cli.output, &apikey::invalidate(&client, &es, key_ids)?)?;
+                    }
+                },
+                ServerlessCmd::FleetBootstrap { id, policy_name, export, timeout, fleet_image } => {
+                    let inv = Inventory::open_default()?;
+                    let sl = match inv.entries().iter().find(|e| e.id == *id) {
+                        Some(entry) => serverless_for_entry(&client, entry)?,
+                        None => client.serverless()?
+                    };
+                    let project = sl.get(id).context("error fetching project")?;
+                    let image = fleet::fleet_image(id, project.overrides.as_ref(), fleet_image.as_deref())?;
+                    let kibana = dataplane::resolve(&client, id, Service::Kibana)?;
+                    let res = fleet::bootstrap(&client, &kibana, policy_name, image, (*timeout).into()).context("error bootstrapping Fleet")?;
+                    if *export {
+                        println!("{}", res.exports());
+                    } else {
+                        print_generic_struct(&cli.output, &res)?;
                     }
-                   }
-
                 },
                 ServerlessCmd::Raw(raw) => {
                     let parsed = cfg.resolve_serverless();
-                    let res = handle_raw_request(parsed, raw, client).context("error performing raw HTTP request")?;
-                    println!("{}", res);
+                    let res = handle_raw_request(parsed, raw, &client).context("error performing raw HTTP request")?;
+                    print_response(&cli.output, &res, raw.include)?;
+                    check_status(&res)?;
+                }
+            }
+        },
+        cli::Types::Mine => {
+            let inv = Inventory::open_default()?;
+            let items = inv.entries().iter().map(|entry| {
+                ResourceStatus { entry: entry.clone(), status: live_status(&client, entry) }
+            }).collect();
+            print_generic_struct(&cli.output, &ResourceStatusList { items })?;
+        },
+        cli::Types::Cleanup => {
+            let inv = Inventory::open_default()?;
+            let mut failed = 0;
+            for entry in inv.entries() {
+                if let Err(e) = remove_resource(&client, entry.kind, &entry.id, &entry.name, entry.project_type.as_deref()) {
+                    error!("could not remove {} {} ({}): {:#}", entry.kind, entry.name, entry.id, e);
+                    failed += 1;
+                }
+            }
+            if failed > 0 {
+                return Err(anyhow!("{} resources could not be removed", failed))
+            }
+        },
+        cli::Types::Events { serverless, stateful, interval } => {
+            // with neither flag, watch everything
+            let (serverless, stateful) = (*serverless || !*stateful, *stateful || !*serverless);
+            events::stream((*interval).into(), || {
+                let mut poll = Poll::default();
+                if serverless {
+                    serverless_snapshots(&client, &mut poll)?;
+                }
+                if stateful {
+                    stateful_snapshots(&client, &mut poll)?;
+                }
+                Ok(poll)
+            }, std::io::stdout())?;
+        },
+        cli::Types::Es { id, req } => {
+            let target = dataplane::resolve(&client, id, Service::Elasticsearch)?;
+            let res = target.send(&client, req.parts()?).context("error performing Elasticsearch request")?;
+            print_response(&cli.output, &res, req.include)?;
+            check_status(&res)?;
+        },
+        cli::Types::Kibana { id, space, req } => {
+            let target = dataplane::resolve(&client, id, Service::Kibana)?;
+            let mut parts = req.parts()?;
+            parts.path = dataplane::kibana_path(&parts.path, space.as_deref());
+            let res = target.send(&client, parts).context("error performing Kibana request")?;
+            print_response(&cli.output, &res, req.include)?;
+            check_status(&res)?;
+        },
+        cli::Types::Run { recipe, args, include } => {
+            let saved = cfg.recipes.get(recipe).ok_or_else(|| {
+                let names: Vec<&str> = cfg.recipes.keys().map(String::as_str).collect();
+                anyhow!("no recipe named '{}' in the config file, found: {}", recipe, names.join(", "))
+            })?;
+            let raw = recipe::build_request(saved, &cfg.config.project, args, *include)?;
+            let endpoint = match saved.target {
+                RecipeTarget::Serverless => cfg.resolve_serverless(),
+                RecipeTarget::Stateful => cfg.resolve_stateful()
+            };
+            let res = handle_raw_request(endpoint, &raw, &client).with_context(|| format!("error running recipe {}", recipe))?;
+            print_response(&cli.output, &res, raw.include)?;
+            check_status(&res)?;
+        },
+        cli::Types::Reap => {
+            let now = Utc::now();
+            let mut failed = 0;
+
+            // serverless projects have no user-defined metadata, so their expiry only lives in the local state file
+            let inv = Inventory::open_default()?;
+            for entry in inv.entries().iter().filter(|e| e.kind == ResourceKind::Serverless) {
+                let expires = match entry.expires_at {
+                    Some(exp) if exp <= now => exp,
+                    _ => continue
+                };
+                info!("project {} ({}) expired at {}", entry.name, entry.id, expires.to_rfc3339());
+                if let Err(e) = remove_resource(&client, entry.kind, &entry.id, &entry.name, entry.project_type.as_deref()) {
+                    error!("could not delete expired project {} ({}): {:#}", entry.name, entry.id, e);
+                    failed += 1;
+                }
+            }
+
+            // stateful deployments carry the expiry in their metadata tags, so expired deployments are found
+            // no matter which machine created them
+            let deployments = client.stateful()?.list().context("error listing deployments")?;
+            for listing in deployments.deployments {
+                // most deployments aren't ours to reap, so failing to read one is no reason to fail the run
+                let dep = match client.stateful()?.get(&listing.id) {
+                    Result::Ok(dep) => dep,
+                    Err(e) => {
+                        warn!("skipping deployment {} ({}), could not fetch it: {:#}", listing.name, listing.id, e);
+                        continue;
+                    }
+                };
+                if dep.metadata.as_ref().and_then(|m| m.hidden).unwrap_or(false) {
+                    continue;
+                }
+                let expires = match deployment_expiry(&dep) {
+                    Some(exp) if exp <= now => exp,
+                    _ => continue
+                };
+                info!("deployment {} ({}) expired at {}", dep.name, dep.id, expires.to_rfc3339());
+                if let Err(e) = remove_resource(&client, ResourceKind::Stateful, &dep.id, &dep.name, None) {
+                    error!("could not shut down expired deployment {} ({}): {:#}", dep.name, dep.id, e);
+                    failed += 1;
+                }
+            }
+
+            if failed > 0 {
+                return Err(anyhow!("{} expired resources could not be removed", failed))
+            }
+        },
+        cli::Types::Seed { id, dir, batch_size } => {
+            let fixtures = seed::plan(dir)?;
+            if fixtures.is_empty() {
+                return Err(anyhow!("no fixtures found in {}", dir.display()))
+            }
+            let es = dataplane::resolve(&client, id, Service::Elasticsearch)?;
+            let kibana = match fixtures.iter().any(|f| f.kind == seed::FixtureKind::SavedObjects) {
+                true => Some(dataplane::resolve(&client, id, Service::Kibana)?),
+                false => None
+            };
+            let report = seed::seed(&client, &es, kibana.as_ref(), &fixtures, *batch_size);
+            print_generic_struct(&cli.output, &report)?;
+            if report.failed() > 0 {
+                return Err(anyhow!("{} of {} fixtures could not be loaded", report.failed(), report.results.len()))
+            }
+        },
+        cli::Types::Apply { file, prune, plan } => {
+            let plan = match (file, plan) {
+                (_, Some(path)) => {
+                    let plan = manifest::read_plan(path)?;
+                    manifest::check_stale(&client, &plan)?;
+                    plan
+                },
+                (Some(file), None) => {
+                    let manifest = manifest::load(file)?;
+                    manifest::plan(&client, &cfg, &manifest, *prune).context("error comparing the manifest to live resources")?
+                },
+                (None, None) => return Err(anyhow!("either --file or --plan is required"))
+            };
+            let mut results = Vec::new();
+            for action in plan.actions {
+                let res = match action.op {
+                    Op::Unchanged => Ok(action.id.clone()),
+                    _ => {
+                        info!("{} {} {}", action.op, action.kind, action.name);
+                        apply_action(&client, &cfg, &plan.label, &action)
+                    }
+                };
+                results.push(match res {
+                    Result::Ok(id) => ApplyResult { op: action.op, kind: action.kind, name: action.name, id, ok: true, error: None },
+                    // the request was only printed
+                    Err(e) if is_dry_run(&e) => continue,
+                    Err(e) => {
+                        error!("could not {} {} {}: {:#}", action.op, action.kind, action.name, e);
+                        ApplyResult { op: action.op, kind: action.kind, name: action.name, id: action.id, ok: false, error: Some(format!("{:#}", e)) }
+                    }
+                });
+            }
+            let report = ApplyReport { results };
+            print_generic_struct(&cli.output, &report)?;
+            if report.failed() > 0 {
+                return Err(anyhow!("{} of {} changes could not be applied", report.failed(), report.results.len()))
+            }
+        },
+        cli::Types::Plan { file, prune } => {
+            let manifest = manifest::load(file)?;
+            let plan = manifest::plan(&client, &cfg, &manifest, *prune).context("error comparing the manifest to live resources")?;
+            // the diff is the default view; other formats print the plan itself
+            if matches!(cli.output.out, OutputType::Struct) && cli.output.query.is_none() && cli.output.template.is_none() && !cli.output.quiet {
+                print!("{}", redact::redact(&plan.render(std::io::stdout().is_terminal())));
+            } else {
+                print_generic_struct(&cli.output, &plan)?;
+            }
+        },
+        cli::Types::Pool { pool_file, cmd } => {
+            let pool = pool::Pool::open(pool_file.as_deref())?;
+            match cmd {
+                PoolCmd::Fill { size, project_type, region } => {
+                    let project_type = project_type.clone().unwrap_or_else(|| cfg.config.project.clone());
+                    let region = region.clone().or_else(|| cfg.resolve_serverless().region)
+                        .ok_or_else(|| anyhow!("region value must be set in CLI or config"))?;
+                    pool::fill(&client, &pool, &project_type, &region, *size, &cfg.resolve_serverless().url)?;
+                    print_generic_struct(&cli.output, &pool::PoolStatus::new(pool.entries()?))?;
+                },
+                PoolCmd::Checkout { project_type, region, lease, holder, export } => {
+                    let project_type = project_type.clone().unwrap_or_else(|| cfg.config.project.clone());
+                    let holder = holder.clone().or_else(|| std::env::var("USER").ok()).unwrap_or_else(|| "unknown".to_string());
+                    let res = pool::checkout(&client, &pool, &project_type, region.as_deref(), &holder, (*lease).into())?;
+                    if *export {
+                        println!("{}", res.exports());
+                    } else {
+                        print_generic_struct(&cli.output, &res)?;
+                    }
+                },
+                PoolCmd::Checkin { id, destroy } => {
+                    pool::checkin(&client, &pool, id, *destroy)?;
+                },
+                PoolCmd::Status => {
+                    print_generic_struct(&cli.output, &pool::PoolStatus::new(pool.entries()?))?;
                 }
             }
         }
@@ -141,35 +533,217 @@ fn main() -> Result<()>{
     Ok(())
 }
 
-fn handle_raw_request(cfg: DeploymentSpecificConfig, raw: &RawReq, client: ESSClient) -> Result<String> {
+fn handle_raw_request(cfg: DeploymentSpecificConfig, raw: &RawReq, client: &ESSClient) -> Result<RawResponse> {
     let endpoint = Url::parse(&cfg.url)?;
-    let res = match raw {
-        cli::RawReq::Get { path } => {
-            client.get(&endpoint, path)?
-            
-        },
-        cli::RawReq::Post { path, body }=> {
-            client.post(&endpoint, path, body.clone())?
+    client.request(&endpoint, raw.parts()?)
+}
+
+/// fail with the response status if a raw request got an error response. The body has already been printed.
+fn check_status(res: &RawResponse) -> Result<()> {
+    if res.status.is_client_error() || res.status.is_server_error() {
+        return Err(HttpStatusError{status: res.status}.into())
+    }
+    Ok(())
+}
+
+/// save credentials for a newly created or reset resource in the local store.
+/// The resource already exists at this point, so a store failure only warns, and prints the
+/// credentials unmasked to stderr since they can't be fetched again later.
+fn save_credentials(id: &str, creds: StoredCredentials) {
+    let saved = CredentialStore::open_default().context("error opening credential store").and_then(|mut store| {
+        store.insert(id, creds.clone());
+        store.save().context("error saving credentials to local store")
+    });
+    if let Err(e) = saved {
+        warn!("credentials for {} were not saved: {:#}", id, e);
+        eprintln!("credentials for {} (username,password): {}", id, creds.compact());
+    }
+}
+
+/// add a newly created resource to the local inventory.
+/// The resource already exists at this point, so a failure only warns rather than failing the create.
+fn record_resource(entry: InventoryEntry) {
+    let id = entry.id.clone();
+    let saved = Inventory::open_default().context("error opening local state file").and_then(|mut inv| {
+        inv.record(entry);
+        inv.save().context("error saving local state file")
+    });
+    if let Err(e) = saved {
+        warn!("{} was not added to the local state file: {:#}", id, e);
+    }
+}
+
+/// drop any stored credentials and inventory entries for a deleted resource.
+/// The resource is already gone at this point, so failures are only logged.
+fn forget_resource(id: &str) {
+    let res = CredentialStore::open_default().and_then(|mut store| {
+        if store.remove(id).is_some() {
+            store.save()?;
         }
+        Ok(())
+    });
+    if let Err(e) = res {
+        warn!("could not remove credentials for {} from local store: {:#}", id, e);
+    }
+
+    let res = Inventory::open_default().and_then(|mut inv| {
+        if inv.remove(id).is_some() {
+            inv.save()?;
+        }
+        Ok(())
+    });
+    if let Err(e) = res {
+        warn!("could not remove {} from local state file: {:#}", id, e);
+    }
+}
+
+/// return a serverless handler for the project type an inventory entry was created with
+fn serverless_for_entry<'a>(client: &'a ESSClient, entry: &InventoryEntry) -> Result<ServerlessClient<'a>> {
+    match &entry.project_type {
+        Some(project) => client.serverless_project(project),
+        None => client.serverless()
+    }
+}
+
+/// delete a serverless project or shut down a deployment, and forget about it locally.
+/// Resources that no longer exist are treated as removed.
+fn remove_resource(client: &ESSClient, kind: ResourceKind, id: &str, name: &str, project_type: Option<&str>) -> Result<()> {
+    let res = match kind {
+        ResourceKind::Serverless => project_type.map_or_else(|| client.serverless(), |p| client.serverless_project(p))
+            .and_then(|sl| sl.delete(id)),
+        ResourceKind::Stateful => client.stateful()
+            .and_then(|sf| sf.shutdown(id)).map(|_| ())
     };
-    let formatted = jsonxf::pretty_print(&res).map_err(|v|anyhow!("{}", v))?;
-    Ok(formatted)
+    match res {
+        Result::Ok(_) => info!("removed {} {} ({})", kind, name, id),
+        Err(e) if is_not_found(&e) => info!("{} {} ({}) no longer exists, pruning", kind, name, id),
+        // nothing was removed, so keep the local records
+        Err(e) if is_dry_run(&e) => return Ok(()),
+        Err(e) => return Err(e)
+    }
+    forget_resource(id);
+    Ok(())
 }
 
-fn print_generic_struct<P>(form: cli::OutputType, item: &P) -> Result<()> 
-where P: Serialize + std::fmt::Debug + ResultFormatting
-{
-    match form {
-        cli::OutputType::Compact => {
-            println!("{}", item.compact());
+/// carry out a single step of a manifest plan, returning the ID of the resource
+fn apply_action(client: &ESSClient, cfg: &config::Config, label: &str, action: &Action) -> Result<Option<String>> {
+    let missing = || anyhow!("the plan for {} {} has no ID or desired state", action.kind, action.name);
+    match (action.op, action.kind) {
+        (Op::Create, ResourceKind::Serverless) => {
+            let project_type = action.project_type.clone().unwrap_or_else(|| cfg.config.project.clone());
+            let sl = client.serverless_project(&project_type)?;
+            let res = sl.create(action.project.clone().ok_or_else(missing)?, false).context("error creating project")?;
+            let mut entry = InventoryEntry::new(&res.id, ResourceKind::Serverless, &res.name, Some(res.region_id.clone()), &cfg.resolve_serverless().url);
+            entry.project_type = Some(res.project_type.clone());
+            entry.manifest = Some(label.to_string());
+            record_resource(entry);
+            let creds = sl.reset_credentials(&res.id).context("error resetting credentials")?;
+            save_credentials(&res.id, StoredCredentials::Serverless(creds));
+            Ok(Some(res.id))
         },
-        cli::OutputType::Json => {
-            let res = serde_json::to_string_pretty(&item)?;
-            println!("{}", res);
+        (Op::Update, ResourceKind::Serverless) => {
+            let id = action.id.as_deref().ok_or_else(missing)?;
+            let project_type = action.project_type.clone().unwrap_or_else(|| cfg.config.project.clone());
+            let overrides = action.project.as_ref().and_then(|p| p.overrides.clone());
+            client.serverless_project(&project_type)?.update(id, UpdateProject { name: None, overrides }).context("error updating project")?;
+            Ok(Some(id.to_string()))
         },
-        cli::OutputType::Struct => {
-            println!("{:#?}", item);
-        }
+        (Op::Create, ResourceKind::Stateful) => {
+            let resp = client.stateful()?.create(action.deployment.clone().ok_or_else(missing)?).context("error creating deployment")?;
+            let created_region = resp.resources.iter().find_map(|r| r.region.clone());
+            let mut entry = InventoryEntry::new(&resp.id, ResourceKind::Stateful, &resp.name, created_region, &cfg.resolve_stateful().url);
+            entry.manifest = Some(label.to_string());
+            record_resource(entry);
+            if let Some(creds) = resp.resources.iter().find_map(|r| r.credentials.clone()) {
+                save_credentials(&resp.id, StoredCredentials::Stateful(creds));
+            }
+            Ok(Some(resp.id))
+        },
+        (Op::Update, ResourceKind::Stateful) => {
+            let id = action.id.as_deref().ok_or_else(missing)?;
+            let req = action.update.clone().ok_or_else(missing)?;
+            client.stateful()?.update(id, req).context("error updating deployment")?;
+            Ok(Some(id.to_string()))
+        },
+        (Op::Delete, kind) => {
+            let id = action.id.as_deref().ok_or_else(missing)?;
+            remove_resource(client, kind, id, &action.name, action.project_type.as_deref())?;
+            Ok(Some(id.to_string()))
+        },
+        (Op::Unchanged, _) => Ok(action.id.clone())
+    }
+}
+
+/// fetch a short, human-readable status for an inventory entry
+fn live_status(client: &ESSClient, entry: &InventoryEntry) -> String {
+    let res = match entry.kind {
+        ResourceKind::Serverless => serverless_for_entry(client, entry)
+            .and_then(|sl| sl.status(&entry.id)).map(|s| s.phase),
+        ResourceKind::Stateful => client.stateful()
+            .and_then(|sf| sf.get(&entry.id))
+            .map(|d| if d.healthy { "healthy".to_string() } else { "unhealthy".to_string() })
+    };
+    match res {
+        Result::Ok(status) => status,
+        Err(e) if is_not_found(&e) => "not found".to_string(),
+        Err(e) => format!("error: {}", e)
+    }
+}
+
+/// snapshot every serverless project of every type along with its current phase
+fn serverless_snapshots(client: &ESSClient, poll: &mut Poll) -> Result<()> {
+    // the configured type and any type recorded in the inventory may not be one of the known types yet
+    let mut types = vec![client.serverless()?.project];
+    types.extend(PROJECT_TYPES.iter().map(|t| t.to_string()));
+    types.extend(Inventory::open_default()?.entries().iter().filter_map(|e| e.project_type.clone()));
+    let mut seen = std::collections::HashSet::new();
+    types.retain(|t| seen.insert(t.clone()));
+    for project_type in types {
+        serverless_type_snapshots(&client.serverless_project(&project_type)?, poll)?;
+    }
+    Ok(())
+}
+
+/// snapshot every serverless project of a single type
+fn serverless_type_snapshots(sl: &ServerlessClient, poll: &mut Poll) -> Result<()> {
+    let projects = sl.list().context(format!("error listing {} projects", sl.project))?;
+    for p in projects.items {
+        let phase = match sl.status(&p.id) {
+            Result::Ok(status) => status.phase,
+            Err(e) => {
+                warn!("error fetching status for project {} ({}): {:#}", p.name, p.id, e);
+                poll.failed.push((ResourceKind::Serverless, p.id));
+                continue;
+            }
+        };
+        let endpoints = [("elasticsearch", &p.endpoints.elasticsearch), ("kibana", &p.endpoints.kibana), ("apm", &p.endpoints.apm)]
+            .into_iter()
+            .filter(|(_, url)| !url.is_empty())
+            .map(|(name, url)| (name.to_string(), url.clone()))
+            .collect();
+        poll.snapshots.push(Snapshot { kind: ResourceKind::Serverless, id: p.id, name: p.name, phase: Some(phase), healthy: None, endpoints });
+    }
+    Ok(())
+}
+
+/// snapshot every stateful deployment along with its current health
+fn stateful_snapshots(client: &ESSClient, poll: &mut Poll) -> Result<()> {
+    let sf = client.stateful()?;
+    let deployments = sf.list().context("error listing deployments")?;
+    for listing in deployments.deployments {
+        let dep = match sf.get(&listing.id) {
+            Result::Ok(dep) => dep,
+            Err(e) => {
+                warn!("error fetching deployment {} ({}): {:#}", listing.name, listing.id, e);
+                poll.failed.push((ResourceKind::Stateful, listing.id));
+                continue;
+            }
+        };
+        let endpoints = [("elasticsearch", dep.elasticsearch_url()), ("kibana", dep.kibana_url())]
+            .into_iter()
+            .filter_map(|(name, url)| Some((name.to_string(), url?)))
+            .collect();
+        poll.snapshots.push(Snapshot { kind: ResourceKind::Stateful, id: dep.id, name: dep.name, phase: None, healthy: Some(dep.healthy), endpoints });
     }
     Ok(())
 }
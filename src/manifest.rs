@@ -0,0 +1,788 @@
+use std::{collections::{BTreeMap, HashSet}, fs, path::Path};
+
+use anyhow::{Result, Context, anyhow};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+
+use crate::{clients::{client::{ESSClient, ResultFormatting, TableFormatting, ListFormatting, headers, list_rows, is_not_found},
+    serverless::{CreateProject, Project, ProjectOverrides}, create_deployment::{DeploymentCreateRequest, DeploymentUpdateRequest},
+    deployments::{DeploymentGetResponse, DeploymentMetadata, ResourceInfo}}, config::Config, state::{Inventory, ResourceKind}, watch::{GREEN, YELLOW, RED, RESET}};
+
+/// deployment metadata tag that holds the label of the manifest a stateful deployment belongs to
+pub const MANIFEST_TAG: &str = "esscli_manifest";
+
+/// the components of a deployment whose docker image can be overridden
+const DOCKER_COMPONENTS: &[&str] = &["elasticsearch", "kibana", "integrations_server"];
+
+/// A test environment, described in a YAML or TOML file and reconciled with `esscli apply`
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Manifest {
+    /// marks the resources created from this manifest, so ones removed from it can be found with --prune
+    pub label: String,
+    #[serde(default)]
+    pub serverless: Vec<ServerlessSpec>,
+    #[serde(default)]
+    pub stateful: Vec<StatefulSpec>
+}
+
+/// A serverless project in a manifest
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ServerlessSpec {
+    pub name: String,
+    /// the project type, e.g. observability. Defaults to the project type in the config file
+    #[serde(rename = "type")]
+    pub project_type: Option<String>,
+    /// defaults to the serverless region in the config file
+    pub region: Option<String>,
+    pub overrides: Option<ProjectOverrides>
+}
+
+/// A stateful deployment in a manifest
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct StatefulSpec {
+    pub name: String,
+    /// the path of a deployment create request to start from. Defaults to default_deployment in the config file
+    pub template: Option<String>,
+    pub region: Option<String>,
+    pub version: Option<String>,
+    /// docker image overrides, keyed by elasticsearch, kibana or integrations_server
+    #[serde(default)]
+    pub docker_images: BTreeMap<String, String>
+}
+
+/// read a manifest. Files ending in .toml are read as TOML, anything else as YAML.
+pub fn load(path: &Path) -> Result<Manifest> {
+    let raw = fs::read_to_string(path).context(format!("error reading manifest {}", path.display()))?;
+    let is_toml = path.extension().is_some_and(|e| e == "toml");
+    parse(&raw, is_toml).context(format!("error reading manifest {}", path.display()))
+}
+
+fn parse(raw: &str, is_toml: bool) -> Result<Manifest> {
+    let manifest: Manifest = match is_toml {
+        true => toml::from_str(raw)?,
+        false => serde_yaml::from_str(raw)?
+    };
+    if manifest.label.trim().is_empty() {
+        return Err(anyhow!("the manifest label can't be empty"))
+    }
+    let mut seen = HashSet::new();
+    for name in manifest.serverless.iter().map(|s| &s.name) {
+        if !seen.insert(name) {
+            return Err(anyhow!("serverless project {} is listed more than once", name))
+        }
+    }
+    let mut seen = HashSet::new();
+    for spec in &manifest.stateful {
+        if !seen.insert(&spec.name) {
+            return Err(anyhow!("deployment {} is listed more than once", spec.name))
+        }
+        if let Some(component) = spec.docker_images.keys().find(|c| !DOCKER_COMPONENTS.contains(&c.as_str())) {
+            return Err(anyhow!("deployment {} has a docker image for unknown component {}, expected one of: {}",
+                spec.name, component, DOCKER_COMPONENTS.join(", ")))
+        }
+    }
+    Ok(manifest)
+}
+
+/// What `apply` will do to a resource
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Op {
+    Create,
+    Update,
+    Delete,
+    Unchanged
+}
+
+impl std::fmt::Display for Op {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Op::Create => write!(f, "create"),
+            Op::Update => write!(f, "update"),
+            Op::Delete => write!(f, "delete"),
+            Op::Unchanged => write!(f, "unchanged")
+        }
+    }
+}
+
+/// A field that differs between a resource and the manifest
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Change {
+    pub field: String,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    /// whether applying the change restarts the resource's nodes one by one, rather than happening in place
+    #[serde(default)]
+    pub restart: bool
+}
+
+/// A single step of reconciling the manifest
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Action {
+    pub op: Op,
+    pub kind: ResourceKind,
+    pub name: String,
+    /// the ID of the existing resource, unset for creates
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// the serverless project type
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<Change>,
+    /// the desired project, for serverless creates and updates
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project: Option<CreateProject>,
+    /// the desired deployment, for stateful creates
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deployment: Option<DeploymentCreateRequest>,
+    /// the update request for a stateful update, built from the deployment's current plan
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub update: Option<DeploymentUpdateRequest>
+}
+
+impl Action {
+    /// whether any of the changes restart the resource
+    pub fn restart(&self) -> bool {
+        self.changes.iter().any(|c| c.restart)
+    }
+
+    /// the desired state of the resource as JSON, for creates and updates
+    fn desired(&self) -> Option<Value> {
+        match (&self.project, &self.deployment) {
+            (Some(project), _) => serde_json::to_value(project).ok(),
+            (None, Some(dep)) => serde_json::to_value(dep).ok(),
+            (None, None) => None
+        }
+    }
+}
+
+/// Everything `apply` will do to bring the live resources in line with a manifest.
+/// Written as JSON by `esscli plan -o json`, and read back by `esscli apply --plan`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Plan {
+    pub label: String,
+    pub actions: Vec<Action>
+}
+
+/// read a plan written by `esscli plan -o json`
+pub fn read_plan(path: &Path) -> Result<Plan> {
+    let raw = fs::read_to_string(path).context(format!("error reading plan {}", path.display()))?;
+    serde_json::from_str(&raw).context(format!("{} is not a plan written by `esscli plan -o json`", path.display()))
+}
+
+impl Plan {
+    /// render the plan as a diff: `+` for creates, `~` for updates and `-` for deletes, with changes that
+    /// restart the resource marked. Creates list every field of the desired state.
+    pub fn render(&self, color: bool) -> String {
+        let paint = |code: &str, text: String| if color && !code.is_empty() { format!("{}{}{}", code, text, RESET) } else { text };
+        let mut out = String::new();
+        for action in &self.actions {
+            let id = action.id.as_ref().map(|id| format!(" ({})", id)).unwrap_or_default();
+            let (sign, code) = match action.op {
+                Op::Create => ("+", GREEN),
+                Op::Update => ("~", YELLOW),
+                Op::Delete => ("-", RED),
+                Op::Unchanged => ("=", "")
+            };
+            let restart = if action.restart() { ", rolling restart" } else { "" };
+            out.push_str(&paint(code, format!("{} {} {} {}{}{}\n", sign, action.op, action.kind, action.name, id, restart)));
+            match action.op {
+                Op::Create => {
+                    for (field, val) in action.desired().map(|d| flatten(&d)).unwrap_or_default() {
+                        out.push_str(&paint(GREEN, format!("    + {}: {}\n", field, val)));
+                    }
+                },
+                Op::Update => {
+                    for change in &action.changes {
+                        let when = if change.restart { "rolling restart" } else { "in place" };
+                        out.push_str(&paint(YELLOW, format!("    ~ {}: {} -> {} ({})\n", change.field,
+                            change.from.as_deref().unwrap_or("(unset)"), change.to.as_deref().unwrap_or("(unset)"), when)));
+                    }
+                },
+                Op::Delete | Op::Unchanged => ()
+            }
+        }
+        let count = |op| self.actions.iter().filter(|a| a.op == op).count();
+        let restarts = self.actions.iter().filter(|a| a.op == Op::Update && a.restart()).count();
+        out.push_str(&format!("\n{} to create, {} to update ({} with a rolling restart), {} to delete, {} unchanged\n",
+            count(Op::Create), count(Op::Update), restarts, count(Op::Delete), count(Op::Unchanged)));
+        out
+    }
+}
+
+/// flatten a JSON value into `path: value` pairs, e.g. `resources.elasticsearch[0].region: gcp-us-central1`
+fn flatten(val: &Value) -> Vec<(String, String)> {
+    fn walk(val: &Value, path: String, out: &mut Vec<(String, String)>) {
+        match val {
+            Value::Object(map) => {
+                for (key, v) in map {
+                    walk(v, if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) }, out);
+                }
+            },
+            Value::Array(items) => {
+                for (i, v) in items.iter().enumerate() {
+                    walk(v, format!("{}[{}]", path, i), out);
+                }
+            },
+            Value::Null => (),
+            Value::String(s) => out.push((path, s.clone())),
+            other => out.push((path, other.to_string()))
+        }
+    }
+    let mut out = Vec::new();
+    walk(val, String::new(), &mut out);
+    out
+}
+
+impl ResultFormatting for Plan {
+    fn compact(&self) -> String {
+        self.render(false)
+    }
+}
+
+impl ListFormatting for Plan {
+    fn list_rows(&self) -> Result<Vec<Value>> {
+        list_rows(&self.actions)
+    }
+}
+
+impl TableFormatting for Plan {
+    fn headers(&self) -> Vec<String> {
+        headers(&["OP", "KIND", "NAME", "ID", "CHANGES", "RESTART"])
+    }
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.actions.iter().map(|a| vec![
+            a.op.to_string(), a.kind.to_string(), a.name.clone(), a.id.clone().unwrap_or_default(),
+            a.changes.iter().map(|c| c.field.as_str()).collect::<Vec<&str>>().join(","), a.restart().to_string()
+        ]).collect()
+    }
+}
+
+/// compare a manifest against the live projects and deployments.
+/// With `prune`, resources carrying the manifest's label that it no longer lists are deleted.
+pub fn plan(client: &ESSClient, cfg: &Config, manifest: &Manifest, prune: bool) -> Result<Plan> {
+    let mut actions = Vec::new();
+    // serverless projects can't carry a label, so the ones created from this manifest are found in the local state file
+    let inv = Inventory::open_default().context("error opening local state file")?;
+    let labelled_project = |id: &str| inv.entries().iter()
+        .any(|e| e.kind == ResourceKind::Serverless && e.id == id && e.manifest.as_deref() == Some(manifest.label.as_str()));
+
+    let sl_cfg = cfg.resolve_serverless();
+    // projects are listed once per project type
+    let mut listed: BTreeMap<String, Vec<Project>> = BTreeMap::new();
+    for spec in &manifest.serverless {
+        let project_type = spec.project_type.clone().unwrap_or_else(|| cfg.config.project.clone());
+        let region = spec.region.clone().or_else(|| sl_cfg.region.clone())
+            .ok_or_else(|| anyhow!("no region for project {}, set one in the manifest or config file", spec.name))?;
+        let desired = CreateProject { name: spec.name.clone(), region_id: region, overrides: spec.overrides.clone() };
+        if !listed.contains_key(&project_type) {
+            let projects = client.serverless_project(&project_type)?.list().context(format!("error listing {} projects", project_type))?;
+            listed.insert(project_type.clone(), projects.items);
+        }
+        // only projects created from this manifest are adopted, never someone else's project with the same name
+        let projects = &listed[&project_type];
+        let existing = match projects.iter().filter(|p| p.name == spec.name).find(|p| labelled_project(&p.id)) {
+            Some(project) => Some(project),
+            None => match projects.iter().find(|p| p.name == spec.name) {
+                Some(other) => return Err(anyhow!("serverless project name {} is already in use by {}, which wasn't created from manifest {}",
+                    spec.name, other.id, manifest.label)),
+                None => None
+            }
+        };
+        let (op, id, changes, project) = match existing {
+            None => (Op::Create, None, Vec::new(), desired),
+            Some(project) => {
+                if project.region_id != desired.region_id {
+                    warn!("project {} is in {} rather than {}, and can't be moved", project.name, project.region_id, desired.region_id);
+                }
+                // components the manifest doesn't mention keep their current overrides
+                let merged = CreateProject { name: project.name.clone(), region_id: project.region_id.clone(),
+                    overrides: merge_overrides(project.overrides.as_ref(), desired.overrides.as_ref()) };
+                let changes = diff(&project_payload(project), &serde_json::to_value(&merged)?, "");
+                (if changes.is_empty() { Op::Unchanged } else { Op::Update }, Some(project.id.clone()), changes, merged)
+            }
+        };
+        actions.push(Action { op, kind: ResourceKind::Serverless, name: spec.name.clone(), id, project_type: Some(project_type),
+            changes, project: Some(project), deployment: None, update: None });
+    }
+
+    if prune {
+        for entry in inv.entries().iter().filter(|e| e.kind == ResourceKind::Serverless && e.manifest.as_deref() == Some(manifest.label.as_str())) {
+            if actions.iter().any(|a| a.id.as_deref() == Some(entry.id.as_str())) {
+                continue;
+            }
+            actions.push(Action { op: Op::Delete, kind: ResourceKind::Serverless, name: entry.name.clone(), id: Some(entry.id.clone()),
+                project_type: entry.project_type.clone(), changes: Vec::new(), project: None, deployment: None, update: None });
+        }
+    }
+
+    if !manifest.stateful.is_empty() || prune {
+        let deployments = live_deployments(client)?;
+        let labelled = |dep: &DeploymentGetResponse| dep.metadata.as_ref().and_then(|m| m.tag(MANIFEST_TAG)) == Some(manifest.label.as_str());
+        for spec in &manifest.stateful {
+            let desired = deployment_request(cfg, &manifest.label, spec)?;
+            // as with projects, a deployment is only adopted if it carries the manifest's label
+            let existing = match deployments.iter().filter(|d| d.name == spec.name).find(|d| labelled(d)) {
+                Some(dep) => Some(dep),
+                None => match deployments.iter().find(|d| d.name == spec.name) {
+                    Some(other) => return Err(anyhow!("deployment name {} is already in use by {}, which wasn't created from manifest {}",
+                        spec.name, other.id, manifest.label)),
+                    None => None
+                }
+            };
+            let action = |op, id, changes, deployment, update| Action { op, kind: ResourceKind::Stateful, name: spec.name.clone(), id,
+                project_type: None, changes, project: None, deployment, update };
+            actions.push(match existing {
+                None => action(Op::Create, None, Vec::new(), Some(desired), None),
+                Some(dep) => {
+                    let (changes, update) = deployment_update(dep, spec, &desired, &manifest.label)?;
+                    match changes.is_empty() {
+                        true => action(Op::Unchanged, Some(dep.id.clone()), changes, None, None),
+                        false => action(Op::Update, Some(dep.id.clone()), changes, None, Some(update))
+                    }
+                }
+            });
+        }
+        if prune {
+            for dep in &deployments {
+                if labelled(dep) && !manifest.stateful.iter().any(|s| s.name == dep.name) {
+                    actions.push(Action { op: Op::Delete, kind: ResourceKind::Stateful, name: dep.name.clone(), id: Some(dep.id.clone()),
+                        project_type: None, changes: Vec::new(), project: None, deployment: None, update: None });
+                }
+            }
+        }
+    }
+
+    Ok(Plan { label: manifest.label.clone(), actions })
+}
+
+/// fetch every deployment that hasn't been shut down
+fn live_deployments(client: &ESSClient) -> Result<Vec<DeploymentGetResponse>> {
+    let sf = client.stateful()?;
+    let mut deployments = Vec::new();
+    for listing in sf.list().context("error listing deployments")?.deployments {
+        let dep = match sf.get(&listing.id) {
+            Result::Ok(dep) => dep,
+            // shut down between the list and the get
+            Err(e) if is_not_found(&e) => continue,
+            Err(e) => return Err(e.context(format!("error fetching deployment {}", listing.id)))
+        };
+        if !dep.metadata.as_ref().and_then(|m| m.hidden).unwrap_or(false) {
+            deployments.push(dep);
+        }
+    }
+    info!("found {} deployments", deployments.len());
+    Ok(deployments)
+}
+
+/// build the create request for a deployment from its template, labelled with the manifest
+fn deployment_request(cfg: &Config, label: &str, spec: &StatefulSpec) -> Result<DeploymentCreateRequest> {
+    let template = spec.template.clone().unwrap_or_else(|| cfg.config.default_deployment.clone());
+    let mut req = crate::create_deployment_request_from_cli(spec.region.clone(), Some(spec.name.clone()), None, template)
+        .context(format!("error creating request for deployment {}", spec.name))?;
+    if let Some(version) = &spec.version {
+        req.set_version(version);
+    }
+    for (component, image) in &spec.docker_images {
+        req.set_docker_image(component, image);
+    }
+    req.metadata.get_or_insert(DeploymentMetadata { tags: None, hidden: None }).set_tag(MANIFEST_TAG, label);
+    Ok(req)
+}
+
+/// the update that brings a deployment in line with the manifest, along with every field it changes. It starts from the
+/// plans the deployment currently runs and only changes what has drifted: the version and docker images the manifest sets,
+/// and the topology sizes of the template. Resources without changes are left out, and the existing metadata tags,
+/// such as the expiry, are kept.
+fn deployment_update(dep: &DeploymentGetResponse, spec: &StatefulSpec, desired: &DeploymentCreateRequest, label: &str)
+    -> Result<(Vec<Change>, DeploymentUpdateRequest)> {
+    let desired = serde_json::to_value(desired)?;
+    let mut changes = Vec::new();
+    let mut resources = Map::new();
+    for component in DOCKER_COMPONENTS {
+        let mut changed = Vec::new();
+        for (i, res) in component_resources(dep, component).iter().enumerate() {
+            let Some(current) = resource_payload(res) else {
+                continue
+            };
+            let mut payload = current.clone();
+            if let Some(version) = &spec.version {
+                payload["plan"][*component]["version"] = Value::String(version.clone());
+            }
+            if let Some(image) = spec.docker_images.get(*component) {
+                payload["plan"][*component]["docker_image"] = Value::String(image.clone());
+            }
+            let template = desired["resources"][*component].as_array()
+                .and_then(|items| items.iter().find(|r| r["ref_id"] == res.ref_id.as_str()));
+            if let Some(template) = template {
+                copy_sizes(&mut payload["plan"], &template["plan"]);
+            }
+            let resource_changes = diff(&current, &payload, &format!("resources.{}[{}]", component, i));
+            if !resource_changes.is_empty() {
+                changes.extend(resource_changes);
+                changed.push(payload);
+            }
+        }
+        if !changed.is_empty() {
+            resources.insert(component.to_string(), Value::Array(changed));
+        }
+    }
+    let mut metadata = DeploymentMetadata { tags: dep.metadata.as_ref().and_then(|m| m.tags.clone()), hidden: None };
+    metadata.set_tag(MANIFEST_TAG, label);
+    Ok((changes, DeploymentUpdateRequest { name: None, prune_orphans: false, resources: Value::Object(resources), metadata: Some(metadata) }))
+}
+
+/// copy the size and zone count of each topology element in a template plan onto the matching element of a current plan.
+/// Elasticsearch elements are matched by ID, others by position.
+fn copy_sizes(plan: &mut Value, template: &Value) {
+    let Some(wanted) = template["cluster_topology"].as_array() else {
+        return
+    };
+    let Some(current) = plan["cluster_topology"].as_array_mut() else {
+        return
+    };
+    for (i, want) in wanted.iter().enumerate() {
+        let found = match want["id"].as_str() {
+            Some(id) => current.iter_mut().find(|c| c["id"] == id),
+            None => current.get_mut(i)
+        };
+        let Some(element) = found else {
+            continue
+        };
+        for key in ["size", "zone_count"] {
+            if !want[key].is_null() {
+                element[key] = want[key].clone();
+            }
+        }
+    }
+}
+
+/// the resources of a deployment of one kind, e.g. `kibana`
+fn component_resources<'a>(dep: &'a DeploymentGetResponse, component: &str) -> &'a [ResourceInfo] {
+    match component {
+        "elasticsearch" => &dep.resources.elasticsearch,
+        "kibana" => &dep.resources.kibana,
+        _ => &dep.resources.integrations_server
+    }
+}
+
+/// a resource as it currently runs, in the shape of a create or update request payload
+fn resource_payload(res: &ResourceInfo) -> Option<Value> {
+    let mut payload = json!({"region": res.region, "ref_id": res.ref_id, "plan": res.current_plan()?});
+    if let Some(es) = &res.elasticsearch_cluster_ref_id {
+        payload["elasticsearch_cluster_ref_id"] = Value::String(es.clone());
+    }
+    Some(payload)
+}
+
+/// every field of a deployment's current resources, named as in the changes of a plan
+fn deployment_fields(dep: &DeploymentGetResponse) -> BTreeMap<String, String> {
+    let mut fields = BTreeMap::new();
+    for component in DOCKER_COMPONENTS {
+        for (i, res) in component_resources(dep, component).iter().enumerate() {
+            let prefix = format!("resources.{}[{}]", component, i);
+            if let Some(payload) = resource_payload(res) {
+                fields.extend(flatten(&payload).into_iter().map(|(field, val)| (format!("{}.{}", prefix, field), val)));
+            }
+        }
+    }
+    fields
+}
+
+/// a project as it currently is, in the shape of a create request
+fn project_payload(project: &Project) -> Value {
+    json!({"name": project.name, "region_id": project.region_id, "overrides": project.overrides})
+}
+
+/// lay the overrides a manifest sets over a project's current ones
+fn merge_overrides(current: Option<&ProjectOverrides>, desired: Option<&ProjectOverrides>) -> Option<ProjectOverrides> {
+    let Some(desired) = desired else {
+        return current.cloned()
+    };
+    let mut merged = current.cloned().unwrap_or_default();
+    merged.elasticsearch = desired.elasticsearch.clone().or(merged.elasticsearch);
+    merged.kibana = desired.kibana.clone().or(merged.kibana);
+    merged.fleet = desired.fleet.clone().or(merged.fleet);
+    Some(merged)
+}
+
+/// every field that differs between two JSON values, prefixed with `prefix`
+fn diff(before: &Value, after: &Value, prefix: &str) -> Vec<Change> {
+    let before: BTreeMap<String, String> = flatten(before).into_iter().collect();
+    let after: BTreeMap<String, String> = flatten(after).into_iter().collect();
+    let fields: BTreeMap<&String, ()> = before.keys().chain(after.keys()).map(|k| (k, ())).collect();
+    fields.into_keys()
+        .filter(|f| before.get(*f) != after.get(*f))
+        .map(|f| {
+            let field = if prefix.is_empty() { f.clone() } else { format!("{}.{}", prefix, f) };
+            Change { restart: restarts(&field), field, from: before.get(f).cloned(), to: after.get(f).cloned() }
+        })
+        .collect()
+}
+
+/// whether changing a field restarts or replaces the resource's nodes one by one, rather than being applied in place.
+/// That's the case for versions, docker images, sizes, zones and node settings; names, tags and the like change in place.
+fn restarts(field: &str) -> bool {
+    let last = field.rsplit('.').next().unwrap_or(field);
+    RESTART_FIELDS.contains(&last) || field.ends_with(".size.value") || field.ends_with(".size.resource") || field.contains("user_settings")
+}
+
+/// the last part of the name of a field whose change restarts the resource
+const RESTART_FIELDS: &[&str] = &["version", "docker_image", "zone_count", "instance_configuration_id"];
+
+/// fail if a saved plan no longer matches the live resources: a resource it creates now exists, or a resource it
+/// updates or deletes is gone or has changed since the plan was made
+pub fn check_stale(client: &ESSClient, plan: &Plan) -> Result<()> {
+    let stale = |action: &Action, why: String| anyhow!("the plan is out of date: {} {} {}, run plan again", action.kind, action.name, why);
+    let mut deployments: Option<Vec<DeploymentGetResponse>> = None;
+    for action in plan.actions.iter().filter(|a| a.op != Op::Unchanged) {
+        let project_type = action.project_type.as_deref().unwrap_or_default();
+        match (action.op, action.kind, action.id.as_deref()) {
+            (Op::Create, ResourceKind::Serverless, _) => {
+                let projects = client.serverless_project(project_type)?.list().context("error listing projects")?;
+                if let Some(p) = projects.items.iter().find(|p| p.name == action.name) {
+                    return Err(stale(action, format!("now exists as {}", p.id)))
+                }
+            },
+            (Op::Create, ResourceKind::Stateful, _) => {
+                if deployments.is_none() {
+                    deployments = Some(live_deployments(client)?);
+                }
+                if let Some(d) = deployments.iter().flatten().find(|d| d.name == action.name) {
+                    return Err(stale(action, format!("now exists as {}", d.id)))
+                }
+            },
+            (_, kind, Some(id)) => {
+                let current = match kind {
+                    ResourceKind::Serverless => client.serverless_project(project_type)?.get(id)
+                        .map(|p| flatten(&project_payload(&p)).into_iter().collect()),
+                    ResourceKind::Stateful => client.stateful()?.get(id).map(|d| deployment_fields(&d))
+                };
+                let current: BTreeMap<String, String> = match current {
+                    Result::Ok(current) => current,
+                    Err(e) if is_not_found(&e) => return Err(stale(action, format!("({}) no longer exists", id))),
+                    Err(e) => return Err(e.context(format!("error fetching {}", id)))
+                };
+                if let Some(change) = action.changes.iter().find(|c| current.get(&c.field) != c.from.as_ref()) {
+                    return Err(stale(action, format!("({}) has changed: {} is now {}", id, change.field,
+                        current.get(&change.field).map(String::as_str).unwrap_or("(unset)"))))
+                }
+            },
+            (_, _, None) => return Err(anyhow!("the plan for {} {} has no ID", action.kind, action.name))
+        }
+    }
+    Ok(())
+}
+
+/// The outcome of a single action
+#[derive(Serialize, Debug, Clone)]
+pub struct ApplyResult {
+    pub op: Op,
+    pub kind: ResourceKind,
+    pub name: String,
+    pub id: Option<String>,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ApplyReport {
+    pub results: Vec<ApplyResult>
+}
+
+impl ApplyReport {
+    pub fn failed(&self) -> usize {
+        self.results.iter().filter(|r| !r.ok).count()
+    }
+}
+
+impl ResultFormatting for ApplyReport {
+    fn compact(&self) -> String {
+        let mut acc = String::new();
+        for res in &self.results {
+            let status = if res.ok { "ok".to_string() } else { format!("failed: {}", res.error.as_deref().unwrap_or("")) };
+            acc = format!("{}{} {} {}: {}\n", acc, res.op, res.kind, res.name, status);
+        }
+        acc
+    }
+}
+
+impl ListFormatting for ApplyReport {
+    fn list_rows(&self) -> Result<Vec<Value>> {
+        list_rows(&self.results)
+    }
+}
+
+impl TableFormatting for ApplyReport {
+    fn headers(&self) -> Vec<String> {
+        headers(&["OP", "KIND", "NAME", "ID", "OK", "ERROR"])
+    }
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.results.iter().map(|r| vec![
+            r.op.to_string(), r.kind.to_string(), r.name.clone(), r.id.clone().unwrap_or_default(), r.ok.to_string(), r.error.clone().unwrap_or_default()
+        ]).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clients::serverless::{ApplicationOverride, ProjectOverrides};
+
+    use crate::{clients::serverless::CreateProject, state::ResourceKind};
+
+    use crate::clients::deployments::DeploymentGetResponse;
+
+    use super::{parse, merge_overrides, diff, deployment_update, flatten, restarts, Action, Change, Op, Plan, StatefulSpec, MANIFEST_TAG};
+
+    #[test]
+    fn test_parse() {
+        let yaml = r#"
+label: team-env
+serverless:
+  - name: obs-1
+    type: observability
+    overrides:
+      elasticsearch:
+        docker_image: docker.elastic.co/elasticsearch:test
+stateful:
+  - name: dep-1
+    version: 8.11.1
+    docker_images:
+      kibana: docker.elastic.co/kibana:test
+"#;
+        let manifest = parse(yaml, false).unwrap();
+        assert_eq!(manifest.label, "team-env");
+        assert_eq!(manifest.serverless[0].project_type.as_deref(), Some("observability"));
+        assert_eq!(manifest.stateful[0].docker_images["kibana"], "docker.elastic.co/kibana:test");
+
+        let toml = r#"
+label = "team-env"
+[[serverless]]
+name = "search-1"
+type = "elasticsearch"
+region = "aws-eu-west-1"
+"#;
+        let manifest = parse(toml, true).unwrap();
+        assert_eq!(manifest.serverless[0].region.as_deref(), Some("aws-eu-west-1"));
+        assert!(manifest.stateful.is_empty());
+
+        assert!(parse("label: x\nserverless: [{name: a}, {name: a}]", false).is_err());
+        assert!(parse("label: x\nstateful: [{name: a, docker_images: {apm: img}}]", false).is_err());
+    }
+
+    #[test]
+    fn test_project_diff() {
+        let overrides = |es: &str| ProjectOverrides {
+            elasticsearch: Some(ApplicationOverride { docker_image: es.to_string() }), kibana: None, fleet: None
+        };
+        let project = |overrides: Option<ProjectOverrides>| serde_json::to_value(
+            CreateProject { name: "obs-1".to_string(), region_id: "aws-eu-west-1".to_string(), overrides }).unwrap();
+        let changes = |current: Option<ProjectOverrides>, desired: Option<ProjectOverrides>| {
+            let merged = merge_overrides(current.as_ref(), desired.as_ref());
+            diff(&project(current), &project(merged), "")
+        };
+        assert!(changes(None, None).is_empty());
+        assert!(changes(Some(overrides("a")), Some(overrides("a"))).is_empty());
+        // components the manifest doesn't set are left alone
+        assert!(changes(Some(overrides("a")), Some(ProjectOverrides::default())).is_empty());
+
+        let changes = changes(None, Some(overrides("b")));
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].field, "overrides.elasticsearch.docker_image");
+        assert_eq!(changes[0].from, None);
+        assert_eq!(changes[0].to.as_deref(), Some("b"));
+        assert!(changes[0].restart);
+    }
+
+    #[test]
+    fn test_restarts() {
+        assert!(restarts("resources.elasticsearch[0].plan.elasticsearch.version"));
+        assert!(restarts("resources.kibana[0].plan.kibana.docker_image"));
+        assert!(restarts("resources.elasticsearch[0].plan.cluster_topology[0].size.value"));
+        assert!(restarts("resources.elasticsearch[0].plan.elasticsearch.user_settings_yaml"));
+        assert!(!restarts("name"));
+        assert!(!restarts("resources.elasticsearch[0].plan.autoscaling_enabled"));
+    }
+
+    #[test]
+    fn test_render_plan() {
+        let action = |op, name: &str| Action { op, kind: ResourceKind::Serverless, name: name.to_string(), id: None,
+            project_type: Some("observability".to_string()), changes: Vec::new(), project: None, deployment: None, update: None };
+        let mut create = action(Op::Create, "new");
+        create.project = Some(CreateProject { name: "new".to_string(), region_id: "aws-eu-west-1".to_string(), overrides: None });
+        let mut update = action(Op::Update, "old");
+        update.id = Some("abc".to_string());
+        update.changes = vec![Change { field: "overrides.kibana.docker_image".to_string(), from: None, to: Some("img".to_string()), restart: true }];
+        let plan = Plan { label: "team-env".to_string(), actions: vec![create, update] };
+
+        let out = plan.render(false);
+        assert!(out.contains("+ create serverless new\n    + name: new\n    + region_id: aws-eu-west-1\n"));
+        assert!(out.contains("~ update serverless old (abc), rolling restart\n    ~ overrides.kibana.docker_image: (unset) -> img (rolling restart)\n"));
+        assert!(out.ends_with("1 to create, 1 to update (1 with a rolling restart), 0 to delete, 0 unchanged\n"));
+
+        // a saved plan is read back unchanged by apply --plan
+        let read: Plan = serde_json::from_str(&serde_json::to_string(&plan).unwrap()).unwrap();
+        assert_eq!(read.actions[1].changes, plan.actions[1].changes);
+        assert_eq!(read.actions[0].project, plan.actions[0].project);
+    }
+
+    #[test]
+    fn test_flatten() {
+        let val = serde_json::json!({"name": "dep", "resources": {"elasticsearch": [{"region": "gcp", "size": 4096, "alias": null}]}});
+        assert_eq!(flatten(&val), vec![
+            ("name".to_string(), "dep".to_string()),
+            ("resources.elasticsearch[0].region".to_string(), "gcp".to_string()),
+            ("resources.elasticsearch[0].size".to_string(), "4096".to_string())
+        ]);
+    }
+
+    #[test]
+    fn test_deployment_update() {
+        let dep: DeploymentGetResponse = serde_json::from_value(serde_json::json!({
+            "id": "abc", "name": "dep-1", "healthy": true,
+            "resources": {
+                "elasticsearch": [{"ref_id": "main-elasticsearch", "id": "es1", "region": "gcp-us-central1", "info": {"plan_info": {"current": {"plan": {
+                    "cluster_topology": [{"id": "hot_content", "size": {"value": 8192, "resource": "memory"}}],
+                    "elasticsearch": {"version": "8.11.0"},
+                    "deployment_template": {"id": "gcp-storage-optimized"}
+                }}}}}],
+                "kibana": [{"ref_id": "main-kibana", "id": "kb1", "region": "gcp-us-central1", "elasticsearch_cluster_ref_id": "main-elasticsearch",
+                    "info": {"plan_info": {"current": {"plan": {"kibana": {"version": "8.11.0", "docker_image": "kibana:test"}}}}}}]
+            },
+            "metadata": {"tags": [{"key": "esscli_expires_at", "value": "2030-01-01T00:00:00Z"}, {"key": MANIFEST_TAG, "value": "team-env"}]}
+        })).unwrap();
+        let spec = StatefulSpec { name: "dep-1".to_string(), template: None, region: None, version: None,
+            docker_images: [("elasticsearch".to_string(), "es:test".to_string()), ("kibana".to_string(), "kibana:test".to_string())].into() };
+        let template = serde_json::from_value(serde_json::json!({"resources": {"elasticsearch": [{
+            "region": "gcp-us-central1", "ref_id": "main-elasticsearch",
+            "plan": {"cluster_topology": [{"id": "hot_content", "size": {"value": 8192, "resource": "memory"}}], "elasticsearch": {"version": "8.9.0"}}
+        }]}})).unwrap();
+
+        let (changes, req) = deployment_update(&dep, &spec, &template, "team-env").unwrap();
+        // kibana already runs the image, so only elasticsearch is sent, without the template's version
+        assert!(req.resources.get("kibana").is_none());
+        let es = &req.resources["elasticsearch"][0];
+        assert_eq!(es["ref_id"], "main-elasticsearch");
+        assert_eq!(es["plan"]["elasticsearch"]["docker_image"], "es:test");
+        assert_eq!(es["plan"]["elasticsearch"]["version"], "8.11.0");
+        assert_eq!(es["plan"]["cluster_topology"][0]["size"]["value"], 8192);
+        assert_eq!(es["plan"]["deployment_template"]["id"], "gcp-storage-optimized");
+        let fields: Vec<&str> = changes.iter().map(|c| c.field.as_str()).collect();
+        assert_eq!(fields, vec!["resources.elasticsearch[0].plan.elasticsearch.docker_image"]);
+
+        // a resize in the template shows up as a change to the size alone
+        let mut template = template;
+        template.resources.as_mut().unwrap().elasticsearch.as_mut().unwrap()[0].plan.cluster_topology[0].size.as_mut().unwrap().value = 16384;
+        let (changes, req) = deployment_update(&dep, &spec, &template, "team-env").unwrap();
+        assert_eq!(req.resources["elasticsearch"][0]["plan"]["cluster_topology"][0]["size"]["value"], 16384);
+        let resize = changes.iter().find(|c| c.field == "resources.elasticsearch[0].plan.cluster_topology[0].size.value").unwrap();
+        assert_eq!((resize.from.as_deref(), resize.to.as_deref(), resize.restart), (Some("8192"), Some("16384"), true));
+
+        let metadata = req.metadata.unwrap();
+        assert_eq!(metadata.tag("esscli_expires_at"), Some("2030-01-01T00:00:00Z"));
+        assert_eq!(metadata.tag(MANIFEST_TAG), Some("team-env"));
+    }
+}
@@ -0,0 +1,284 @@
+use std::io::{IsTerminal, Write};
+
+use anyhow::{Result, Context, anyhow};
+use prettytable::{Cell, Row, Table, format::consts::FORMAT_NO_LINESEP_WITH_TITLE};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::{cli::{OutputArgs, OutputType}, clients::client::{ResultFormatting, TableFormatting, ListFormatting, RawResponse}, query::Query, redact, template::Template};
+
+/// columns are never truncated below this width when fitting a table to the terminal
+const MIN_COLUMN_WIDTH: usize = 8;
+
+/// print a result in the format requested on the command line
+pub fn print_generic_struct<P>(opts: &OutputArgs, item: &P) -> Result<()>
+where P: Serialize + std::fmt::Debug + ResultFormatting + TableFormatting + ListFormatting
+{
+    redact::register_fields(&serde_json::to_value(item)?);
+    if opts.quiet {
+        return print_ids(&item.list_rows()?)
+    }
+    if let Some(query) = &opts.query {
+        return print_query(opts, query, &serde_json::to_value(item)?)
+    }
+    if let Some(template) = &opts.template {
+        return print_template(template, &item.list_rows()?)
+    }
+    match opts.out {
+        OutputType::Compact => {
+            println!("{}", redact::redact(&item.compact()));
+        },
+        OutputType::Json => {
+            let res = serde_json::to_string_pretty(&item)?;
+            println!("{}", res);
+        },
+        OutputType::Struct => {
+            println!("{}", redact::redact(&format!("{:#?}", item)));
+        },
+        OutputType::Table => {
+            print!("{}", redact::redact(&render_table(&item.headers(), &item.rows(), opts.no_headers, terminal_width())));
+        },
+        OutputType::Yaml => {
+            print!("{}", serde_yaml::to_string(&item)?);
+        },
+        OutputType::Csv => {
+            print!("{}", render_delimited(&item.list_rows()?, b',', opts.no_headers)?);
+        },
+        OutputType::Tsv => {
+            print!("{}", render_delimited(&item.list_rows()?, b'\t', opts.no_headers)?);
+        },
+        OutputType::Ndjson => {
+            for row in item.list_rows()? {
+                println!("{}", serde_json::to_string(&row)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// print a raw API response, optionally preceded by its status and headers
+pub fn print_response(opts: &OutputArgs, res: &RawResponse, include: bool) -> Result<()> {
+    if include {
+        println!("{}", res.status);
+        for (name, val) in &res.headers {
+            println!("{}: {}", name, String::from_utf8_lossy(val.as_bytes()));
+        }
+        println!();
+    }
+    match std::str::from_utf8(&res.body) {
+        Result::Ok(body) => print_raw(opts, body),
+        // binary bodies are passed through untouched
+        Err(_) => Ok(std::io::stdout().write_all(&res.body)?)
+    }
+}
+
+/// print the body of a raw API response, pretty-printing it or applying --query.
+/// Secrets are masked in the pretty-printed form, and bodies that aren't JSON are printed as they are.
+pub fn print_raw(opts: &OutputArgs, body: &str) -> Result<()> {
+    let parsed = serde_json::from_str::<Value>(body);
+    if let Result::Ok(val) = &parsed {
+        redact::register_fields(val);
+    }
+    if let Some(query) = &opts.query {
+        let val = parsed.context("--query requires a JSON response")?;
+        return print_query(opts, query, &val)
+    }
+    if let Some(template) = &opts.template {
+        let val = parsed.context("--format requires a JSON response")?;
+        let rows = match val {
+            Value::Array(rows) => rows,
+            other => vec![other]
+        };
+        return print_template(template, &rows)
+    }
+    if parsed.is_err() {
+        print!("{}", redact::redact(body));
+        return Ok(())
+    }
+    let formatted = jsonxf::pretty_print(body).map_err(|v|anyhow!("{}", v))?;
+    println!("{}", redact::redact(&formatted));
+    Ok(())
+}
+
+/// print every result of a query, one JSON value per line, or bare strings with --raw-output
+fn print_query(opts: &OutputArgs, query: &Query, val: &Value) -> Result<()> {
+    for res in query.eval(val)? {
+        match res {
+            Value::String(s) if opts.raw_output => println!("{}", s),
+            other => println!("{}", serde_json::to_string_pretty(&other)?)
+        }
+    }
+    Ok(())
+}
+
+/// print the ID of each row, skipping rows that don't have one
+fn print_ids(rows: &[Value]) -> Result<()> {
+    for id in rows.iter().filter_map(|r| r.get("id").and_then(Value::as_str)) {
+        println!("{}", id);
+    }
+    Ok(())
+}
+
+/// print each row with a user-supplied template
+fn print_template(template: &Template, rows: &[Value]) -> Result<()> {
+    for row in rows {
+        println!("{}", template.render(row)?);
+    }
+    Ok(())
+}
+
+/// render rows as delimited text, with one column per flattened field.
+/// The header is the union of fields across all rows, sorted within each row and in the order they're first seen across rows.
+pub fn render_delimited(rows: &[Value], delimiter: u8, no_headers: bool) -> Result<String> {
+    let flat: Vec<Map<String, Value>> = rows.iter().map(|r| {
+        let mut out = Map::new();
+        flatten("", r, &mut out);
+        out
+    }).collect();
+
+    let mut columns: Vec<&String> = Vec::new();
+    for row in &flat {
+        for key in row.keys() {
+            if !columns.contains(&key) {
+                columns.push(key);
+            }
+        }
+    }
+
+    let mut writer = csv::WriterBuilder::new().delimiter(delimiter).from_writer(vec![]);
+    if !no_headers {
+        writer.write_record(&columns)?;
+    }
+    for row in &flat {
+        writer.write_record(columns.iter().map(|c| match row.get(*c) {
+            None | Some(Value::Null) => String::new(),
+            Some(Value::String(s)) => s.clone(),
+            Some(other) => other.to_string()
+        }))?;
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+/// flatten nested objects into dotted keys, e.g. `endpoints.kibana`. Arrays are kept as JSON values.
+fn flatten(prefix: &str, val: &Value, out: &mut Map<String, Value>) {
+    match val {
+        Value::Object(map) => {
+            for (k, v) in map {
+                let key = if prefix.is_empty() { k.clone() } else { format!("{}.{}", prefix, k) };
+                flatten(&key, v, out);
+            }
+        },
+        other => {
+            let key = if prefix.is_empty() { "value".to_string() } else { prefix.to_string() };
+            out.insert(key, other.clone());
+        }
+    }
+}
+
+/// the width to fit tables to. Output that isn't going to a terminal is never truncated,
+/// unless the width is set explicitly with COLUMNS.
+fn terminal_width() -> Option<usize> {
+    if let Some(cols) = std::env::var("COLUMNS").ok().and_then(|c| c.parse().ok()) {
+        return Some(cols)
+    }
+    if !std::io::stdout().is_terminal() {
+        return None
+    }
+    terminal_size::terminal_size().map(|(w, _)| w.0 as usize)
+}
+
+/// render a table, shrinking the widest columns until the table fits in `max_width`
+pub fn render_table(headers: &[String], rows: &[Vec<String>], no_headers: bool, max_width: Option<usize>) -> String {
+    let mut widths: Vec<usize> = headers.iter()
+        .map(|h| if no_headers { 0 } else { h.chars().count() })
+        .collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    if let Some(max) = max_width {
+        fit_widths(&mut widths, max);
+    }
+
+    let to_row = |cells: &[String]| -> Row {
+        Row::new(cells.iter().zip(&widths).map(|(c, w)| Cell::new(&truncate(c, *w))).collect())
+    };
+    let mut table = Table::new();
+    table.set_format(*FORMAT_NO_LINESEP_WITH_TITLE);
+    if !no_headers {
+        table.set_titles(to_row(headers));
+    }
+    for row in rows {
+        table.add_row(to_row(row));
+    }
+    table.to_string()
+}
+
+fn fit_widths(widths: &mut [usize], max: usize) {
+    // each column adds a separator and one space of padding on either side
+    let overhead = widths.len() * 3 + 1;
+    let mut total = widths.iter().sum::<usize>() + overhead;
+    while total > max {
+        let Some(widest) = widths.iter_mut().max_by_key(|w| **w) else {
+            return
+        };
+        if *widest <= MIN_COLUMN_WIDTH {
+            return
+        }
+        *widest -= 1;
+        total -= 1;
+    }
+}
+
+fn truncate(val: &str, width: usize) -> String {
+    if val.chars().count() <= width {
+        return val.to_string()
+    }
+    let mut out: String = val.chars().take(width.saturating_sub(1)).collect();
+    out.push('…');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{render_table, render_delimited};
+
+    #[test]
+    fn test_render_delimited() {
+        let rows = vec![
+            json!({"id": "abc", "name": "first", "endpoints": {"kibana": "https://kb"}, "tags": ["a", "b"]}),
+            json!({"id": "def", "name": "second, with comma", "alias": null}),
+        ];
+        let res = render_delimited(&rows, b',', false).unwrap();
+        let lines: Vec<&str> = res.lines().collect();
+        assert_eq!(lines[0], "endpoints.kibana,id,name,tags,alias");
+        assert_eq!(lines[1], r#"https://kb,abc,first,"[""a"",""b""]","#);
+        assert_eq!(lines[2], r#",def,"second, with comma",,"#);
+
+        let res = render_delimited(&rows, b'\t', true).unwrap();
+        assert_eq!(res.lines().next().unwrap(), "https://kb\tabc\tfirst\t\"[\"\"a\"\",\"\"b\"\"]\"\t");
+    }
+
+    #[test]
+    fn test_render_table_fits_width() {
+        let headers = vec!["ID".to_string(), "NAME".to_string(), "KIBANA".to_string()];
+        let rows = vec![vec!["abc".to_string(), "a-fairly-long-project-name".to_string(),
+            "https://a-very-long-kibana-endpoint.kb.eu-west-1.aws.qa.elastic.cloud".to_string()]];
+
+        let full = render_table(&headers, &rows, false, None);
+        assert!(full.contains("https://a-very-long-kibana-endpoint.kb.eu-west-1.aws.qa.elastic.cloud"));
+
+        let fitted = render_table(&headers, &rows, false, Some(60));
+        for line in fitted.lines() {
+            assert!(line.chars().count() <= 60, "line too long: {}", line);
+        }
+        assert!(fitted.contains('…'));
+        assert!(fitted.contains("NAME"));
+
+        let no_headers = render_table(&headers, &rows, true, None);
+        assert!(!no_headers.contains("NAME"));
+    }
+}
@@ -0,0 +1,394 @@
+use std::{fs, io::ErrorKind, path::{Path, PathBuf}, time::Duration};
+
+use anyhow::{Result, Context, anyhow};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{clients::{client::{ESSClient, ResultFormatting, TableFormatting, ListFormatting, headers, list_rows, is_not_found}, serverless::CreateProject},
+    config::{FileLock, config_dir, write_private_file}, credentials::{CredentialStore, StoredCredentials}, redact,
+    state::{InventoryEntry, ResourceKind}};
+
+const POOL_FILE: &str = "pool.json";
+
+/// A pre-created serverless project in the pool
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PoolEntry {
+    pub id: String,
+    pub name: String,
+    pub project_type: String,
+    pub region: String,
+    pub created_at: DateTime<Utc>,
+    /// set while the project is checked out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lease: Option<Lease>
+}
+
+/// A claim on a pooled project by a single user or CI job
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Lease {
+    pub holder: String,
+    pub leased_at: DateTime<Utc>,
+    /// after this the lease is stale, and `pool fill` replaces the project
+    pub expires_at: DateTime<Utc>
+}
+
+impl PoolEntry {
+    /// available, leased or expired
+    pub fn state(&self, now: DateTime<Utc>) -> &'static str {
+        match &self.lease {
+            None => "available",
+            Some(lease) if lease.expires_at <= now => "expired",
+            Some(_) => "leased"
+        }
+    }
+
+    fn matches(&self, project_type: &str, region: Option<&str>) -> bool {
+        self.project_type == project_type && region.is_none_or(|r| r == self.region)
+    }
+}
+
+/// The pool file, shared between esscli processes. Every change happens under a lock file next to it,
+/// so the pool can live on a shared filesystem.
+pub struct Pool {
+    path: PathBuf
+}
+
+impl Pool {
+    /// open the pool at the given path, or in the esscli config directory
+    pub fn open(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(p) => p.to_path_buf(),
+            None => config_dir()?.join(POOL_FILE)
+        };
+        Ok(Pool { path })
+    }
+
+    /// all pooled projects
+    pub fn entries(&self) -> Result<Vec<PoolEntry>> {
+        self.update(|entries| Ok(entries.clone()))
+    }
+
+    /// change the pool while holding the lock, writing it back afterwards
+    fn update<T, F>(&self, f: F) -> Result<T>
+    where F: FnOnce(&mut Vec<PoolEntry>) -> Result<T>
+    {
+        let _lock = FileLock::acquire(&self.path.with_extension("lock"))?;
+        let mut entries: Vec<PoolEntry> = match fs::read_to_string(&self.path) {
+            Result::Ok(raw) => serde_json::from_str(&raw).context(format!("error parsing pool file {}", self.path.display()))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(anyhow!(e).context(format!("error reading pool file {}", self.path.display())))
+        };
+        let res = f(&mut entries)?;
+        write_private_file(&self.path, serde_json::to_string_pretty(&entries)?.as_bytes())?;
+        Ok(res)
+    }
+}
+
+/// replace projects with expired leases, then create projects until there are `size` available ones of the
+/// given type and region. Projects are created outside the lock, as each takes minutes.
+pub fn fill(client: &ESSClient, pool: &Pool, project_type: &str, region: &str, size: usize, profile: &str) -> Result<()> {
+    let now = Utc::now();
+    // expired projects leave the pool under the same lock that finds them, so they can't be checked in by their
+    // old holder, or out by another job, while they're being deleted
+    let (expired, available) = pool.update(|entries| {
+        let expired = take_expired(entries, now);
+        let available = entries.iter().filter(|e| e.matches(project_type, Some(region)) && e.lease.is_none()).count();
+        Ok((expired, available))
+    })?;
+    for entry in &expired {
+        info!("lease on {} ({}) held by {} expired, deleting it", entry.name, entry.id, entry.lease.as_ref().map(|l| l.holder.as_str()).unwrap_or(""));
+        if let Err(e) = delete_project(client, entry) {
+            // back into the pool, still expired, so the next fill tries again
+            pool.update(|entries| {
+                entries.push(entry.clone());
+                Ok(())
+            })?;
+            return Err(e)
+        }
+    }
+
+    let needed = size.saturating_sub(available);
+    info!("{} {} projects available in {}, creating {}", available, project_type, region, needed);
+    let sl = client.serverless_project(project_type)?;
+    for i in 0..needed {
+        let name = format!("esscli-pool-{}-{}", Utc::now().format("%Y%m%d%H%M%S"), i);
+        let project = sl.create(CreateProject { name: name.clone(), region_id: region.to_string(), overrides: None }, true)
+            .context(format!("error creating project {}", name))?;
+        // record the project before anything else can fail, so `mine`, `cleanup` and the pool can all find it
+        let mut inv_entry = InventoryEntry::new(&project.id, ResourceKind::Serverless, &project.name, Some(project.region_id.clone()), profile);
+        inv_entry.project_type = Some(project_type.to_string());
+        crate::record_resource(inv_entry);
+        let entry = PoolEntry { id: project.id.clone(), name: project.name.clone(), project_type: project_type.to_string(),
+            region: project.region_id.clone(), created_at: Utc::now(), lease: None };
+        pool.update(|entries| {
+            entries.push(entry);
+            Ok(())
+        })?;
+        info!("added {} ({}) to the pool", project.name, project.id);
+
+        match sl.reset_credentials(&project.id) {
+            Result::Ok(creds) => crate::save_credentials(&project.id, StoredCredentials::Serverless(creds)),
+            // checkout resets the credentials of projects that have none stored
+            Err(e) => warn!("could not reset credentials for {} ({}): {:#}", project.name, project.id, e)
+        }
+    }
+    Ok(())
+}
+
+/// Everything needed to use a checked out project
+#[derive(Serialize, Debug, Clone)]
+pub struct Checkout {
+    pub id: String,
+    pub name: String,
+    pub project_type: String,
+    pub elasticsearch_url: String,
+    pub kibana_url: String,
+    pub username: String,
+    pub password: String,
+    pub lease_expires_at: DateTime<Utc>
+}
+
+impl Checkout {
+    /// shell `export` lines, for use with `eval`
+    pub fn exports(&self) -> String {
+        format!("export ESSCLI_PROJECT_ID='{}'\nexport ELASTICSEARCH_URL='{}'\nexport KIBANA_URL='{}'\nexport ELASTICSEARCH_USERNAME='{}'\nexport ELASTICSEARCH_PASSWORD='{}'",
+            self.id, self.elasticsearch_url, self.kibana_url, self.username, self.password)
+    }
+}
+
+impl ResultFormatting for Checkout {
+    fn compact(&self) -> String {
+        format!("{}, {}, {}", self.id, self.elasticsearch_url, self.kibana_url)
+    }
+}
+
+impl ListFormatting for Checkout {}
+
+impl TableFormatting for Checkout {
+    fn headers(&self) -> Vec<String> {
+        headers(&["ID", "NAME", "ELASTICSEARCH", "KIBANA", "USERNAME", "PASSWORD", "LEASE EXPIRES"])
+    }
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![self.id.clone(), self.name.clone(), self.elasticsearch_url.clone(), self.kibana_url.clone(),
+            self.username.clone(), self.password.clone(), self.lease_expires_at.to_rfc3339()]]
+    }
+}
+
+/// claim an available project of the given type, and optionally region
+pub fn checkout(client: &ESSClient, pool: &Pool, project_type: &str, region: Option<&str>, holder: &str, lease: Duration) -> Result<Checkout> {
+    let now = Utc::now();
+    let expires_at = chrono::Duration::from_std(lease).ok().and_then(|l| now.checked_add_signed(l))
+        .ok_or_else(|| anyhow!("lease of {} is too long", humantime::format_duration(lease)))?;
+    let entry = pool.update(|entries| {
+        let entry = claim(entries, project_type, region, holder, now, expires_at)
+            .ok_or_else(|| anyhow!("no available {} projects in the pool, run `esscli pool fill` to add some", project_type))?;
+        Ok(entry.clone())
+    })?;
+    info!("checked out {} ({}) until {}", entry.name, entry.id, expires_at.to_rfc3339());
+
+    connect(client, &entry, expires_at).inspect_err(|_| {
+        // a project that can't be handed out goes straight back, rather than staying leased until the lease expires
+        let released = pool.update(|entries| {
+            release(entries, &entry.id, holder, now);
+            Ok(())
+        });
+        if let Err(re) = released {
+            warn!("could not return {} ({}) to the pool: {:#}", entry.name, entry.id, re);
+        }
+    })
+}
+
+/// fetch the endpoints and credentials of a claimed project
+fn connect(client: &ESSClient, entry: &PoolEntry, expires_at: DateTime<Utc>) -> Result<Checkout> {
+    let sl = client.serverless_project(&entry.project_type)?;
+    let project = sl.get(&entry.id).context(format!("error fetching project {}", entry.id))?;
+    let store = CredentialStore::open_default().context("error opening credential store")?;
+    let (username, password) = match store.get(&entry.id) {
+        Some(StoredCredentials::Serverless(c)) => (c.username.clone(), c.password.clone()),
+        // filled from another machine sharing the pool file
+        _ => {
+            info!("no stored credentials for project {}, resetting them", entry.id);
+            let creds = sl.reset_credentials(&entry.id).context("error resetting credentials")?;
+            crate::save_credentials(&entry.id, StoredCredentials::Serverless(creds.clone()));
+            (creds.username, creds.password)
+        }
+    };
+    redact::register(&password);
+    Ok(Checkout { id: entry.id.clone(), name: entry.name.clone(), project_type: entry.project_type.clone(),
+        elasticsearch_url: project.endpoints.elasticsearch, kibana_url: project.endpoints.kibana, username, password, lease_expires_at: expires_at })
+}
+
+/// remove and return the entries whose lease has run out
+fn take_expired(entries: &mut Vec<PoolEntry>, now: DateTime<Utc>) -> Vec<PoolEntry> {
+    let (expired, kept) = entries.drain(..).partition(|e| e.state(now) == "expired");
+    *entries = kept;
+    expired
+}
+
+/// lease the oldest available matching project
+fn claim<'a>(entries: &'a mut [PoolEntry], project_type: &str, region: Option<&str>, holder: &str,
+    now: DateTime<Utc>, expires_at: DateTime<Utc>) -> Option<&'a PoolEntry>
+{
+    let entry = entries.iter_mut()
+        .filter(|e| e.matches(project_type, region) && e.lease.is_none())
+        .min_by_key(|e| e.created_at)?;
+    entry.lease = Some(Lease { holder: holder.to_string(), leased_at: now, expires_at });
+    Some(entry)
+}
+
+/// drop the lease `holder` took at `leased_at`, leaving the project alone if it has since been leased again
+fn release(entries: &mut [PoolEntry], id: &str, holder: &str, leased_at: DateTime<Utc>) {
+    if let Some(entry) = entries.iter_mut().find(|e| e.id == id) {
+        if entry.lease.as_ref().is_some_and(|l| l.holder == holder && l.leased_at == leased_at) {
+            entry.lease = None;
+        }
+    }
+}
+
+/// return a checked out project to the pool, or delete it
+pub fn checkin(client: &ESSClient, pool: &Pool, id: &str, destroy: bool) -> Result<()> {
+    let entry = pool.update(|entries| {
+        let pos = entries.iter().position(|e| e.id == id).ok_or_else(|| anyhow!("project {} is not in the pool", id))?;
+        if destroy {
+            return Ok(entries.remove(pos))
+        }
+        entries[pos].lease = None;
+        Ok(entries[pos].clone())
+    })?;
+    if destroy {
+        delete_project(client, &entry)?;
+    } else {
+        info!("returned {} ({}) to the pool", entry.name, entry.id);
+    }
+    Ok(())
+}
+
+/// delete a project that has left the pool, along with its stored credentials and inventory entry
+fn delete_project(client: &ESSClient, entry: &PoolEntry) -> Result<()> {
+    match client.serverless_project(&entry.project_type)?.delete(&entry.id) {
+        Result::Ok(_) => info!("deleted {} ({})", entry.name, entry.id),
+        Err(e) if is_not_found(&e) => info!("{} ({}) no longer exists", entry.name, entry.id),
+        Err(e) => return Err(e.context(format!("error deleting project {}", entry.id)))
+    }
+    crate::forget_resource(&entry.id);
+    Ok(())
+}
+
+/// A pooled project along with its lease state
+#[derive(Serialize, Debug)]
+pub struct PoolEntryStatus {
+    #[serde(flatten)]
+    pub entry: PoolEntry,
+    pub state: String
+}
+
+#[derive(Serialize, Debug)]
+pub struct PoolStatus {
+    pub items: Vec<PoolEntryStatus>
+}
+
+impl PoolStatus {
+    pub fn new(entries: Vec<PoolEntry>) -> Self {
+        let now = Utc::now();
+        PoolStatus { items: entries.into_iter().map(|entry| PoolEntryStatus { state: entry.state(now).to_string(), entry }).collect() }
+    }
+}
+
+impl ResultFormatting for PoolStatus {
+    fn compact(&self) -> String {
+        let mut acc = String::new();
+        for item in &self.items {
+            acc = format!("{}{}, {}, {}, {}\n", acc, item.entry.name, item.entry.id, item.entry.project_type, item.state);
+        }
+        acc
+    }
+}
+
+impl ListFormatting for PoolStatus {
+    fn list_rows(&self) -> Result<Vec<Value>> {
+        list_rows(&self.items)
+    }
+}
+
+impl TableFormatting for PoolStatus {
+    fn headers(&self) -> Vec<String> {
+        headers(&["ID", "NAME", "TYPE", "REGION", "STATE", "HOLDER", "LEASE EXPIRES"])
+    }
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.items.iter().map(|i| vec![
+            i.entry.id.clone(), i.entry.name.clone(), i.entry.project_type.clone(), i.entry.region.clone(), i.state.clone(),
+            i.entry.lease.as_ref().map(|l| l.holder.clone()).unwrap_or_default(),
+            i.entry.lease.as_ref().map(|l| l.expires_at.to_rfc3339()).unwrap_or_default()
+        ]).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use super::{claim, release, take_expired, Pool, PoolEntry, Lease};
+
+    fn entry(id: &str, region: &str, age_mins: i64) -> PoolEntry {
+        PoolEntry { id: id.to_string(), name: id.to_string(), project_type: "observability".to_string(), region: region.to_string(),
+            created_at: Utc::now() - Duration::minutes(age_mins), lease: None }
+    }
+
+    #[test]
+    fn test_claim() {
+        let now = Utc::now();
+        let mut entries = vec![entry("new", "aws-eu-west-1", 1), entry("old", "aws-eu-west-1", 10), entry("us", "aws-us-east-1", 20)];
+        let expires = now + Duration::hours(1);
+        assert_eq!(claim(&mut entries, "observability", Some("aws-eu-west-1"), "ci", now, expires).unwrap().id, "old");
+        assert_eq!(claim(&mut entries, "observability", Some("aws-eu-west-1"), "ci", now, expires).unwrap().id, "new");
+        assert!(claim(&mut entries, "observability", Some("aws-eu-west-1"), "ci", now, expires).is_none());
+        assert!(claim(&mut entries, "security", None, "ci", now, expires).is_none());
+        assert_eq!(claim(&mut entries, "observability", None, "ci", now, expires).unwrap().id, "us");
+
+        assert_eq!(entries[0].state(now), "leased");
+        assert_eq!(entries[0].state(expires), "expired");
+
+        release(&mut entries, "old", "other", now);
+        assert!(entries[1].lease.is_some());
+        release(&mut entries, "old", "ci", now - Duration::minutes(1));
+        assert!(entries[1].lease.is_some());
+        release(&mut entries, "old", "ci", now);
+        assert_eq!(entries[1].state(now), "available");
+    }
+
+    #[test]
+    fn test_take_expired() {
+        let now = Utc::now();
+        let lease = |mins: i64| Some(Lease { holder: "ci".to_string(), leased_at: now - Duration::hours(2), expires_at: now + Duration::minutes(mins) });
+        let mut entries = vec![entry("free", "aws-eu-west-1", 1), entry("leased", "aws-eu-west-1", 1), entry("expired", "aws-eu-west-1", 1)];
+        entries[1].lease = lease(30);
+        entries[2].lease = lease(-30);
+
+        let expired = take_expired(&mut entries, now);
+        assert_eq!(expired.iter().map(|e| e.id.as_str()).collect::<Vec<_>>(), vec!["expired"]);
+        assert_eq!(entries.iter().map(|e| e.id.as_str()).collect::<Vec<_>>(), vec!["free", "leased"]);
+    }
+
+    #[test]
+    fn test_pool_file() {
+        let path = std::env::temp_dir().join(format!("esscli-test-pool-{}", std::process::id())).join("pool.json");
+        let _ = std::fs::remove_file(&path);
+        let pool = Pool::open(Some(&path)).unwrap();
+        assert!(pool.entries().unwrap().is_empty());
+
+        pool.update(|entries| {
+            let mut leased = entry("abc", "aws-eu-west-1", 5);
+            leased.lease = Some(Lease { holder: "ci".to_string(), leased_at: Utc::now(), expires_at: Utc::now() + Duration::hours(1) });
+            entries.push(leased);
+            Ok(())
+        }).unwrap();
+        let entries = Pool::open(Some(&path)).unwrap().entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].lease.as_ref().unwrap().holder, "ci");
+        // the lock is released after each update
+        assert!(!path.with_extension("lock").exists());
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,203 @@
+use anyhow::{Result, anyhow};
+use serde_json::Value;
+
+/// A single step in a path expression
+#[derive(Clone, Debug, PartialEq)]
+enum Step {
+    /// `.key` or `.["key"]`
+    Key(String),
+    /// `.[0]`, negative values count from the end
+    Index(i64),
+    /// `.[]`
+    Iterate
+}
+
+/// A jq-style query, evaluated against the JSON form of a command's output.
+/// Supports field access (`.endpoints.kibana`, `."key"`), indexing (`.[0]`, `.[-1]`),
+/// iteration (`.items[]`) and pipes (`.items[] | .id`).
+#[derive(Clone, Debug)]
+pub struct Query {
+    stages: Vec<Vec<Step>>
+}
+
+/// parse a query expression
+pub fn parse(expr: &str) -> Result<Query> {
+    let stages = split_pipes(expr)?.iter()
+        .map(|stage| parse_path(stage.trim()).map_err(|e| anyhow!("invalid query '{}': {}", expr, e)))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Query { stages })
+}
+
+impl Query {
+    /// evaluate the query, returning every value it produces
+    pub fn eval(&self, input: &Value) -> Result<Vec<Value>> {
+        let mut current = vec![input.clone()];
+        for stage in &self.stages {
+            for step in stage {
+                let mut next = Vec::new();
+                for val in current {
+                    apply_step(step, val, &mut next)?;
+                }
+                current = next;
+            }
+        }
+        Ok(current)
+    }
+}
+
+fn apply_step(step: &Step, val: Value, out: &mut Vec<Value>) -> Result<()> {
+    match (step, val) {
+        (Step::Key(key), Value::Object(mut map)) => out.push(map.remove(key).unwrap_or(Value::Null)),
+        (Step::Key(_), Value::Null) | (Step::Index(_), Value::Null) => out.push(Value::Null),
+        (Step::Index(idx), Value::Array(mut arr)) => {
+            let pos = if *idx < 0 { arr.len() as i64 + idx } else { *idx };
+            if pos >= 0 && (pos as usize) < arr.len() {
+                out.push(arr.swap_remove(pos as usize));
+            } else {
+                out.push(Value::Null);
+            }
+        },
+        (Step::Iterate, Value::Array(arr)) => out.extend(arr),
+        (Step::Iterate, Value::Object(map)) => out.extend(map.into_iter().map(|(_, v)| v)),
+        (Step::Key(key), other) => return Err(anyhow!("cannot get key \"{}\" of {}", key, type_name(&other))),
+        (Step::Index(idx), other) => return Err(anyhow!("cannot index {} with {}", type_name(&other), idx)),
+        (Step::Iterate, other) => return Err(anyhow!("cannot iterate over {}", type_name(&other)))
+    }
+    Ok(())
+}
+
+fn type_name(val: &Value) -> &'static str {
+    match val {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object"
+    }
+}
+
+/// split an expression on pipes that aren't inside quotes
+fn split_pipes(expr: &str) -> Result<Vec<String>> {
+    let mut stages = vec![String::new()];
+    let mut in_quotes = false;
+    for c in expr.chars() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '|' if !in_quotes => {
+                stages.push(String::new());
+                continue;
+            },
+            _ => {}
+        }
+        stages.last_mut().unwrap().push(c);
+    }
+    if in_quotes {
+        return Err(anyhow!("unterminated string in query '{}'", expr))
+    }
+    Ok(stages)
+}
+
+fn parse_path(path: &str) -> Result<Vec<Step>> {
+    let chars: Vec<char> = path.chars().collect();
+    if chars.first() != Some(&'.') {
+        return Err(anyhow!("path must start with '.'"))
+    }
+    let mut steps = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '.' => {
+                i += 1;
+                match chars.get(i) {
+                    // `.[...]`, handled on the next iteration
+                    Some('[') => {},
+                    Some('"') => {
+                        let (key, end) = read_quoted(&chars, i)?;
+                        steps.push(Step::Key(key));
+                        i = end;
+                    },
+                    Some(c) if is_ident(*c) => {
+                        let start = i;
+                        while i < chars.len() && is_ident(chars[i]) {
+                            i += 1;
+                        }
+                        steps.push(Step::Key(chars[start..i].iter().collect()));
+                    },
+                    // a lone `.` is the identity
+                    None if steps.is_empty() && i == 1 => {},
+                    _ => return Err(anyhow!("expected a key after '.'"))
+                }
+            },
+            '[' => {
+                let close = chars[i..].iter().position(|c| *c == ']')
+                    .ok_or_else(|| anyhow!("missing ']'"))? + i;
+                let inner: String = chars[i + 1..close].iter().collect();
+                let inner = inner.trim();
+                if inner.is_empty() {
+                    steps.push(Step::Iterate);
+                } else if inner.starts_with('"') {
+                    let inner_chars: Vec<char> = inner.chars().collect();
+                    let (key, end) = read_quoted(&inner_chars, 0)?;
+                    if end != inner_chars.len() {
+                        return Err(anyhow!("unexpected characters after key in '[{}]'", inner))
+                    }
+                    steps.push(Step::Key(key));
+                } else {
+                    let idx = inner.parse::<i64>().map_err(|_| anyhow!("invalid index '{}'", inner))?;
+                    steps.push(Step::Index(idx));
+                }
+                i = close + 1;
+            },
+            c => return Err(anyhow!("unexpected character '{}'", c))
+        }
+    }
+    Ok(steps)
+}
+
+/// read a double-quoted string starting at `start`, returning it and the index after the closing quote
+fn read_quoted(chars: &[char], start: usize) -> Result<(String, usize)> {
+    let close = chars[start + 1..].iter().position(|c| *c == '"')
+        .ok_or_else(|| anyhow!("unterminated string"))? + start + 1;
+    Ok((chars[start + 1..close].iter().collect(), close + 1))
+}
+
+fn is_ident(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::parse;
+
+    #[test]
+    fn test_query() {
+        let input = json!({
+            "items": [
+                {"id": "abc", "endpoints": {"elasticsearch": "https://es-a"}},
+                {"id": "def", "endpoints": {"elasticsearch": "https://es-b"}}
+            ],
+            "odd key": 1
+        });
+        assert_eq!(parse(".").unwrap().eval(&input).unwrap(), vec![input.clone()]);
+        assert_eq!(parse(".items[].id").unwrap().eval(&input).unwrap(), vec![json!("abc"), json!("def")]);
+        assert_eq!(parse(".items[-1].endpoints.elasticsearch").unwrap().eval(&input).unwrap(), vec![json!("https://es-b")]);
+        assert_eq!(parse(".items[] | .endpoints | .elasticsearch").unwrap().eval(&input).unwrap(), vec![json!("https://es-a"), json!("https://es-b")]);
+        assert_eq!(parse(".\"odd key\"").unwrap().eval(&input).unwrap(), vec![json!(1)]);
+        assert_eq!(parse(".[\"odd key\"]").unwrap().eval(&input).unwrap(), vec![json!(1)]);
+        assert_eq!(parse(".missing.field").unwrap().eval(&input).unwrap(), vec![json!(null)]);
+        assert_eq!(parse(".items[5]").unwrap().eval(&input).unwrap(), vec![json!(null)]);
+        assert!(parse(".items.id").unwrap().eval(&input).is_err());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("items").is_err());
+        assert!(parse(".items[").is_err());
+        assert!(parse(".items[abc]").is_err());
+        assert!(parse(".\"unterminated").is_err());
+        assert!(parse(".items..id").is_err());
+    }
+}
@@ -0,0 +1,90 @@
+use std::{thread, time::{Duration, Instant}};
+
+use anyhow::{Result, Context, anyhow};
+use log::{debug, info};
+use reqwest::{header::HeaderMap, Method};
+use serde_json::Value;
+use url::Url;
+
+use crate::{clients::client::{ESSClient, HttpStatusError, RequestParts, is_dry_run}, dataplane::{self, DataPlaneTarget, Service}, fleet};
+
+/// how often to re-check a component that isn't ready
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// wait until a project's Elasticsearch and Kibana, and optionally its Fleet server, answer requests.
+/// Each component gets its own timeout.
+pub fn wait_ready(client: &ESSClient, id: &str, timeout: Duration, fleet: bool) -> Result<()> {
+    let es = dataplane::resolve(client, id, Service::Elasticsearch)?;
+    probe("Elasticsearch", timeout, || elasticsearch_ready(client, &es))?;
+
+    let kibana = dataplane::resolve(client, id, Service::Kibana)?;
+    probe("Kibana", timeout, || kibana_ready(client, &kibana))?;
+
+    if fleet {
+        probe("Fleet server", timeout, || fleet_server_ready(client, &kibana))?;
+    }
+    Ok(())
+}
+
+/// run a check until it passes or the timeout is reached. Errors other than dry runs count as not ready.
+pub fn probe<F>(name: &str, timeout: Duration, mut check: F) -> Result<()>
+where F: FnMut() -> Result<bool>
+{
+    let start = Instant::now();
+    loop {
+        match check() {
+            Result::Ok(true) => {
+                info!("{} is ready after {}", name, humantime::format_duration(Duration::from_secs(start.elapsed().as_secs())));
+                return Ok(())
+            },
+            Result::Ok(false) => debug!("{} is not ready yet", name),
+            // the check would have changed something, and was only printed
+            Err(e) if is_dry_run(&e) => return Err(e),
+            Err(e) => debug!("{} is not ready yet: {:#}", name, e)
+        }
+        if start.elapsed() > timeout {
+            return Err(anyhow!("{} was not ready after {}", name, humantime::format_duration(timeout)))
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn elasticsearch_ready(client: &ESSClient, es: &DataPlaneTarget) -> Result<bool> {
+    es.call::<Value>(client, Method::GET, "", None)?;
+    Ok(true)
+}
+
+fn kibana_ready(client: &ESSClient, kibana: &DataPlaneTarget) -> Result<bool> {
+    let status: Value = kibana.call(client, Method::GET, "api/status", None)?;
+    Ok(status["status"]["overall"]["level"] == "available")
+}
+
+fn fleet_server_ready(client: &ESSClient, kibana: &DataPlaneTarget) -> Result<bool> {
+    let url = Url::parse(&fleet::fleet_server_url(client, kibana)?)?;
+    let parts = RequestParts { method: Method::GET, path: "api/status".to_string(), params: Vec::new(), headers: HeaderMap::new(), body: None };
+    let res = client.plain_request(&url, parts)?;
+    if res.status.is_client_error() || res.status.is_server_error() {
+        return Err(HttpStatusError{status: res.status}.into())
+    }
+    let status: Value = serde_json::from_slice(&res.body).with_context(|| format!("error reading status from {}", url))?;
+    Ok(status["status"] == "HEALTHY")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::clients::client::{DryRun, is_dry_run};
+
+    use super::probe;
+
+    #[test]
+    fn test_probe() {
+        let mut calls = 0;
+        assert!(probe("test", Duration::ZERO, || { calls += 1; Ok(true) }).is_ok());
+        assert_eq!(calls, 1);
+
+        let res = probe("test", Duration::from_secs(60), || Err(DryRun.into()));
+        assert!(matches!(res, Err(e) if is_dry_run(&e)));
+    }
+}
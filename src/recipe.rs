@@ -0,0 +1,68 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Result, anyhow};
+use regex::{Captures, Regex};
+
+use crate::{cli::{RawReq, parse_method}, config::Recipe};
+
+/// build the raw request for a recipe, filling placeholders from the given arguments.
+/// `{project}` defaults to the project type set in the config file.
+pub fn build_request(recipe: &Recipe, project: &str, args: &[(String, String)], include: bool) -> Result<RawReq> {
+    let mut vars: BTreeMap<&str, &str> = BTreeMap::from([("project", project)]);
+    vars.extend(args.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+
+    Ok(RawReq {
+        method: parse_method(&recipe.method)?,
+        path: fill(&recipe.path, &vars)?,
+        body: None,
+        data: recipe.body.as_ref().map(|b| fill(b, &vars)).transpose()?,
+        headers: Vec::new(),
+        params: Vec::new(),
+        include
+    })
+}
+
+/// replace `{name}` placeholders. Braces that don't wrap a plain name, such as those in a JSON body, are left alone.
+fn fill(template: &str, vars: &BTreeMap<&str, &str>) -> Result<String> {
+    let re = Regex::new(r"\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap();
+    let mut missing = Vec::new();
+    let filled = re.replace_all(template, |caps: &Captures| {
+        match vars.get(&caps[1]) {
+            Some(val) => val.to_string(),
+            None => {
+                missing.push(caps[1].to_string());
+                caps[0].to_string()
+            }
+        }
+    });
+    if !missing.is_empty() {
+        return Err(anyhow!("missing value for {}, pass it with --arg {}=<value>", missing.join(", "), missing[0]))
+    }
+    Ok(filled.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{Recipe, RecipeTarget};
+
+    use super::build_request;
+
+    #[test]
+    fn test_build_request() {
+        let recipe = Recipe {
+            target: RecipeTarget::Serverless,
+            method: "post".to_string(),
+            path: "projects/{project}/{id}/_reset-credentials".to_string(),
+            body: Some(r#"{"name": "{name}", "tags": {}}"#.to_string()),
+            description: None
+        };
+        let args = vec![("id".to_string(), "abc".to_string()), ("name".to_string(), "test".to_string())];
+        let req = build_request(&recipe, "elasticsearch", &args, false).unwrap();
+        assert_eq!(req.method, reqwest::Method::POST);
+        assert_eq!(req.path, "projects/elasticsearch/abc/_reset-credentials");
+        assert_eq!(req.data.as_deref(), Some(r#"{"name": "test", "tags": {}}"#));
+
+        let res = build_request(&recipe, "elasticsearch", &args[1..], false);
+        assert!(matches!(res, Err(e) if e.to_string().contains("missing value for id")));
+    }
+}
@@ -0,0 +1,97 @@
+use std::sync::{Mutex, atomic::{AtomicBool, Ordering}};
+
+use serde_json::Value;
+
+/// printed in place of a secret
+pub const MASK: &str = "********";
+
+/// fields in API responses that hold secrets
+const SECRET_FIELDS: &[&str] = &["password", "secret_token", "api_key", "encoded"];
+
+/// values shorter than this are never masked, as they'd match too much unrelated output
+const MIN_SECRET_LEN: usize = 4;
+
+static SECRETS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+static SHOW_SECRETS: AtomicBool = AtomicBool::new(false);
+
+/// stop masking secrets, for --show-secrets
+pub fn set_show_secrets(show: bool) {
+    SHOW_SECRETS.store(show, Ordering::Relaxed);
+}
+
+/// add a value to the set of secrets masked in output and logs.
+/// Under GitHub Actions this also tells the runner to mask the value in the job log.
+pub fn register(secret: &str) {
+    let secret = secret.trim();
+    if secret.len() < MIN_SECRET_LEN {
+        return
+    }
+    let mut secrets = SECRETS.lock().unwrap_or_else(|e| e.into_inner());
+    if secrets.iter().any(|s| s == secret) {
+        return
+    }
+    if std::env::var("GITHUB_ACTIONS").is_ok_and(|v| v == "true") {
+        // stderr, so the mask command never ends up in captured or machine-readable output
+        eprintln!("::add-mask::{}", secret);
+    }
+    secrets.push(secret.to_string());
+}
+
+/// register the values of any secret fields in a serialized result
+pub fn register_fields(val: &Value) {
+    match val {
+        Value::Object(map) => {
+            for (key, v) in map {
+                match v {
+                    Value::String(s) if SECRET_FIELDS.contains(&key.as_str()) => register(s),
+                    other => register_fields(other)
+                }
+            }
+        },
+        Value::Array(items) => items.iter().for_each(register_fields),
+        _ => {}
+    }
+}
+
+/// register the secret fields of a JSON or NDJSON body, so they're masked before the body is logged
+pub fn register_body(body: &[u8]) {
+    let text = String::from_utf8_lossy(body);
+    match serde_json::from_str::<Value>(&text) {
+        Ok(val) => register_fields(&val),
+        Err(_) => text.lines()
+            .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+            .for_each(|val| register_fields(&val))
+    }
+}
+
+/// mask every registered secret in a piece of text
+pub fn redact(text: &str) -> String {
+    if SHOW_SECRETS.load(Ordering::Relaxed) {
+        return text.to_string()
+    }
+    let secrets = SECRETS.lock().unwrap_or_else(|e| e.into_inner());
+    secrets.iter().fold(text.to_string(), |acc, s| acc.replace(s.as_str(), MASK))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{register, register_body, register_fields, redact, MASK};
+
+    #[test]
+    fn test_redact() {
+        register("s3cr3t-api-key");
+        register("ab");
+        register_fields(&json!({"items": [{"username": "admin", "password": "hunter2-pass"}], "secret_token": "tok-123456"}));
+
+        assert_eq!(redact("Authorization: ApiKey s3cr3t-api-key"), format!("Authorization: ApiKey {}", MASK));
+        assert_eq!(redact("admin / hunter2-pass, tok-123456"), format!("admin / {}, {}", MASK, MASK));
+        // too short to be registered
+        assert_eq!(redact("abc"), "abc");
+
+        register_body(br#"{"api_key": "ndjson-key-1"}
+{"encoded": "ndjson-encoded-2"}"#);
+        assert_eq!(redact("ndjson-key-1 ndjson-encoded-2"), format!("{} {}", MASK, MASK));
+    }
+}
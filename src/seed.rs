@@ -0,0 +1,296 @@
+use std::{fs, path::{Path, PathBuf}};
+
+use anyhow::{Result, Context, anyhow};
+use log::{info, error};
+use reqwest::{Method, header::{HeaderMap, HeaderValue, CONTENT_TYPE}};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::clients::client::{ESSClient, RequestParts, is_dry_run, ResultFormatting, TableFormatting, ListFormatting, headers, list_rows};
+use crate::dataplane::DataPlaneTarget;
+
+const MULTIPART_BOUNDARY: &str = "esscli-seed-boundary";
+
+/// The kinds of fixture a seed directory can hold, in the order they're applied.
+/// Pipelines go first, so index templates can refer to them as default pipelines.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum FixtureKind {
+    /// `_ingest/pipeline/<name>.json`
+    Pipeline,
+    /// `_index_template/<name>.json`
+    IndexTemplate,
+    /// `data/<index>.ndjson`, one document per line
+    Documents,
+    /// `saved_objects/*.ndjson`, a Kibana saved objects export
+    SavedObjects
+}
+
+impl std::fmt::Display for FixtureKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FixtureKind::Pipeline => write!(f, "pipeline"),
+            FixtureKind::IndexTemplate => write!(f, "index template"),
+            FixtureKind::Documents => write!(f, "documents"),
+            FixtureKind::SavedObjects => write!(f, "saved objects")
+        }
+    }
+}
+
+/// A single fixture file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fixture {
+    pub kind: FixtureKind,
+    /// the pipeline, template or index name, taken from the file name
+    pub name: String,
+    pub path: PathBuf
+}
+
+/// The outcome of applying a single fixture
+#[derive(Serialize, Debug, Clone)]
+pub struct SeedResult {
+    pub kind: FixtureKind,
+    pub name: String,
+    pub ok: bool,
+    /// documents or objects loaded
+    pub count: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SeedReport {
+    pub results: Vec<SeedResult>
+}
+
+impl SeedReport {
+    pub fn failed(&self) -> usize {
+        self.results.iter().filter(|r| !r.ok).count()
+    }
+}
+
+impl ResultFormatting for SeedReport {
+    fn compact(&self) -> String {
+        let mut acc = String::new();
+        for res in &self.results {
+            let status = if res.ok { "ok".to_string() } else { format!("failed: {}", res.error.as_deref().unwrap_or("")) };
+            acc = format!("{}{} {}: {} ({})\n", acc, res.kind, res.name, status, res.count);
+        }
+        acc
+    }
+}
+
+impl ListFormatting for SeedReport {
+    fn list_rows(&self) -> Result<Vec<Value>> {
+        list_rows(&self.results)
+    }
+}
+
+impl TableFormatting for SeedReport {
+    fn headers(&self) -> Vec<String> {
+        headers(&["KIND", "NAME", "OK", "COUNT", "ERROR"])
+    }
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.results.iter().map(|r| vec![
+            r.kind.to_string(), r.name.clone(), r.ok.to_string(), r.count.to_string(), r.error.clone().unwrap_or_default()
+        ]).collect()
+    }
+}
+
+/// find the fixtures in a seed directory, in the order they should be applied
+pub fn plan(dir: &Path) -> Result<Vec<Fixture>> {
+    if !dir.is_dir() {
+        return Err(anyhow!("seed directory {} does not exist", dir.display()))
+    }
+    let mut fixtures = Vec::new();
+    for (kind, sub, ext) in [
+        (FixtureKind::Pipeline, "_ingest/pipeline", "json"),
+        (FixtureKind::IndexTemplate, "_index_template", "json"),
+        (FixtureKind::Documents, "data", "ndjson"),
+        (FixtureKind::SavedObjects, "saved_objects", "ndjson")
+    ] {
+        let path = dir.join(sub);
+        if !path.is_dir() {
+            continue;
+        }
+        let mut files: Vec<PathBuf> = fs::read_dir(&path).context(format!("error reading {}", path.display()))?
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|e| e == ext))
+            .collect();
+        files.sort();
+        fixtures.extend(files.into_iter().map(|path| Fixture {
+            kind,
+            name: path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default(),
+            path
+        }));
+    }
+    Ok(fixtures)
+}
+
+/// apply every fixture in order. Failures are collected in the report rather than stopping the run.
+pub fn seed(client: &ESSClient, es: &DataPlaneTarget, kibana: Option<&DataPlaneTarget>, fixtures: &[Fixture], batch_size: usize) -> SeedReport {
+    let mut results = Vec::new();
+    for (i, fixture) in fixtures.iter().enumerate() {
+        info!("[{}/{}] loading {} {}", i + 1, fixtures.len(), fixture.kind, fixture.name);
+        let res = match fixture.kind {
+            FixtureKind::Pipeline => put_json(client, es, &format!("_ingest/pipeline/{}", fixture.name), &fixture.path).map(|_| 1),
+            FixtureKind::IndexTemplate => put_json(client, es, &format!("_index_template/{}", fixture.name), &fixture.path).map(|_| 1),
+            FixtureKind::Documents => load_documents(client, es, &fixture.name, &fixture.path, batch_size),
+            FixtureKind::SavedObjects => match kibana {
+                Some(kibana) => import_saved_objects(client, kibana, &fixture.path),
+                None => Err(anyhow!("no Kibana endpoint"))
+            }
+        };
+        results.push(match res {
+            Result::Ok(count) => SeedResult { kind: fixture.kind, name: fixture.name.clone(), ok: true, count, error: None },
+            // the request was only printed
+            Err(e) if is_dry_run(&e) => continue,
+            Err(e) => {
+                error!("could not load {} {}: {:#}", fixture.kind, fixture.name, e);
+                SeedResult { kind: fixture.kind, name: fixture.name.clone(), ok: false, count: 0, error: Some(format!("{:#}", e)) }
+            }
+        });
+    }
+    SeedReport { results }
+}
+
+fn put_json(client: &ESSClient, es: &DataPlaneTarget, path: &str, file: &Path) -> Result<()> {
+    let body: Value = serde_json::from_slice(&fs::read(file)?).context(format!("{} is not valid JSON", file.display()))?;
+    es.call::<Value>(client, Method::PUT, path, Some(body))?;
+    Ok(())
+}
+
+#[derive(Deserialize, Debug)]
+struct BulkResponse {
+    errors: bool,
+    #[serde(default)]
+    items: Vec<Value>
+}
+
+/// bulk-load a file of documents into an index, returning the number of documents loaded
+fn load_documents(client: &ESSClient, es: &DataPlaneTarget, index: &str, file: &Path, batch_size: usize) -> Result<u64> {
+    let raw = fs::read_to_string(file)?;
+    let docs: Vec<&str> = raw.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+    let mut loaded = 0;
+    let mut failed = 0;
+    let mut first_error = None;
+    for batch in docs.chunks(batch_size.max(1)) {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/x-ndjson"));
+        let parts = RequestParts {
+            method: Method::POST,
+            path: format!("{}/_bulk", index),
+            params: Vec::new(),
+            headers,
+            body: Some(bulk_body(batch).into_bytes())
+        };
+        let res = es.send(client, parts)?;
+        if !res.status.is_success() {
+            return Err(anyhow!("bulk request failed with {}: {}", res.status, String::from_utf8_lossy(&res.body)))
+        }
+        let bulk: BulkResponse = serde_json::from_slice(&res.body).context("error reading bulk response")?;
+        let errors: Vec<&Value> = match bulk.errors {
+            true => bulk.items.iter().filter_map(|i| i.as_object()?.values().next()?.get("error")).collect(),
+            false => Vec::new()
+        };
+        failed += errors.len() as u64;
+        if first_error.is_none() {
+            first_error = errors.first().map(|e| e.to_string());
+        }
+        loaded += (batch.len() - errors.len()) as u64;
+        info!("loaded {}/{} documents into {}", loaded, docs.len(), index);
+    }
+    if failed > 0 {
+        return Err(anyhow!("{} of {} documents failed, first error: {}", failed, docs.len(), first_error.unwrap_or_default()))
+    }
+    Ok(loaded)
+}
+
+/// build a bulk request body that creates each document
+fn bulk_body(docs: &[&str]) -> String {
+    docs.iter().map(|d| format!("{{\"create\":{{}}}}\n{}\n", d)).collect()
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ImportResponse {
+    success: bool,
+    #[serde(default)]
+    success_count: u64,
+    #[serde(default)]
+    errors: Vec<Value>
+}
+
+/// import a saved objects export into Kibana, overwriting existing objects
+fn import_saved_objects(client: &ESSClient, kibana: &DataPlaneTarget, file: &Path) -> Result<u64> {
+    let data = fs::read(file)?;
+    let filename = file.file_name().map(|f| f.to_string_lossy().to_string()).unwrap_or_default();
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_str(&format!("multipart/form-data; boundary={}", MULTIPART_BOUNDARY))?);
+    let parts = RequestParts {
+        method: Method::POST,
+        path: "api/saved_objects/_import".to_string(),
+        params: vec![("overwrite".to_string(), "true".to_string())],
+        headers,
+        body: Some(multipart_file(&filename, &data))
+    };
+    let res = kibana.send(client, parts)?;
+    if !res.status.is_success() {
+        return Err(anyhow!("import failed with {}: {}", res.status, String::from_utf8_lossy(&res.body)))
+    }
+    let import: ImportResponse = serde_json::from_slice(&res.body).context("error reading import response")?;
+    if !import.success {
+        return Err(anyhow!("{} objects failed to import, first error: {}", import.errors.len(),
+            import.errors.first().map(|e| e.to_string()).unwrap_or_default()))
+    }
+    Ok(import.success_count)
+}
+
+/// build a multipart/form-data body holding a single file field
+fn multipart_file(filename: &str, data: &[u8]) -> Vec<u8> {
+    let mut body = format!("--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\nContent-Type: application/ndjson\r\n\r\n",
+        MULTIPART_BOUNDARY, filename).into_bytes();
+    body.extend_from_slice(data);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", MULTIPART_BOUNDARY).as_bytes());
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{plan, bulk_body, multipart_file, FixtureKind};
+
+    #[test]
+    fn test_plan() {
+        let dir = std::env::temp_dir().join(format!("esscli-seed-test-{}", std::process::id()));
+        for sub in ["_index_template", "_ingest/pipeline", "data", "saved_objects"] {
+            fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        fs::write(dir.join("_index_template/logs-b.json"), "{}").unwrap();
+        fs::write(dir.join("_index_template/logs-a.json"), "{}").unwrap();
+        fs::write(dir.join("_index_template/README.md"), "").unwrap();
+        fs::write(dir.join("_ingest/pipeline/parse.json"), "{}").unwrap();
+        fs::write(dir.join("data/logs-test.ndjson"), "{}").unwrap();
+        fs::write(dir.join("saved_objects/dashboards.ndjson"), "{}").unwrap();
+
+        let fixtures = plan(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let order: Vec<(FixtureKind, &str)> = fixtures.iter().map(|f| (f.kind, f.name.as_str())).collect();
+        assert_eq!(order, vec![
+            (FixtureKind::Pipeline, "parse"),
+            (FixtureKind::IndexTemplate, "logs-a"),
+            (FixtureKind::IndexTemplate, "logs-b"),
+            (FixtureKind::Documents, "logs-test"),
+            (FixtureKind::SavedObjects, "dashboards")
+        ]);
+    }
+
+    #[test]
+    fn test_bodies() {
+        assert_eq!(bulk_body(&[r#"{"a":1}"#, r#"{"a":2}"#]), "{\"create\":{}}\n{\"a\":1}\n{\"create\":{}}\n{\"a\":2}\n");
+        let body = String::from_utf8(multipart_file("objects.ndjson", b"{}\n")).unwrap();
+        assert!(body.starts_with("--esscli-seed-boundary\r\nContent-Disposition: form-data; name=\"file\"; filename=\"objects.ndjson\"\r\n"));
+        assert!(body.ends_with("{}\n\r\n--esscli-seed-boundary--\r\n"));
+    }
+}
@@ -0,0 +1,199 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::{Result, Context};
+use chrono::{DateTime, Utc};
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::{clients::client::{ResultFormatting, TableFormatting, ListFormatting, headers, list_rows}, config::{config_dir, write_private_file}};
+
+const STATE_FILE: &str = "state.json";
+
+/// The kind of resource tracked in the local inventory
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ResourceKind {
+    Serverless,
+    Stateful
+}
+
+impl std::fmt::Display for ResourceKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResourceKind::Serverless => write!(f, "serverless"),
+            ResourceKind::Stateful => write!(f, "stateful")
+        }
+    }
+}
+
+/// A resource created from this machine
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InventoryEntry {
+    pub id: String,
+    pub kind: ResourceKind,
+    pub name: String,
+    pub region: Option<String>,
+    /// the serverless project type, used to build the API path for the project
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project_type: Option<String>,
+    /// the API endpoint the resource was created against
+    pub profile: String,
+    pub created_at: DateTime<Utc>,
+    /// when the resource should be removed by `esscli reap`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// the label of the manifest that created the resource with `esscli apply`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manifest: Option<String>,
+    /// the esscli invocation that created the resource
+    pub command: String
+}
+
+impl InventoryEntry {
+    /// create a new entry for a resource created by the current process
+    pub fn new(id: &str, kind: ResourceKind, name: &str, region: Option<String>, profile: &str) -> Self {
+        InventoryEntry {
+            id: id.to_string(),
+            kind,
+            name: name.to_string(),
+            region,
+            project_type: None,
+            profile: profile.to_string(),
+            created_at: Utc::now(),
+            expires_at: None,
+            manifest: None,
+            command: std::env::args().collect::<Vec<String>>().join(" ")
+        }
+    }
+}
+
+/// The on-disk list of resources created by esscli on this machine
+pub struct Inventory {
+    path: PathBuf,
+    entries: Vec<InventoryEntry>
+}
+
+impl Inventory {
+    /// load the inventory from the esscli config directory
+    pub fn open_default() -> Result<Self> {
+        Self::open(config_dir()?.join(STATE_FILE))
+    }
+
+    /// load the inventory at the given path. A missing file results in an empty inventory.
+    pub fn open(path: PathBuf) -> Result<Self> {
+        let mut inv = Inventory { path, entries: Vec::new() };
+        if inv.path.exists() {
+            let raw = fs::read_to_string(&inv.path).context(format!("error reading state file at {}", inv.path.display()))?;
+            inv.entries = serde_json::from_str(&raw).context("error parsing state file")?;
+        }
+        Ok(inv)
+    }
+
+    /// all tracked resources, oldest first
+    pub fn entries(&self) -> &[InventoryEntry] {
+        &self.entries
+    }
+
+    /// add a resource, replacing any existing entry with the same ID
+    pub fn record(&mut self, entry: InventoryEntry) {
+        self.entries.retain(|e| e.id != entry.id);
+        self.entries.push(entry);
+    }
+
+    /// stop tracking a resource, returning its entry if it existed
+    pub fn remove(&mut self, id: &str) -> Option<InventoryEntry> {
+        let pos = self.entries.iter().position(|e| e.id == id)?;
+        Some(self.entries.remove(pos))
+    }
+
+    /// write the inventory back to disk
+    pub fn save(&self) -> Result<()> {
+        let out = serde_json::to_string_pretty(&self.entries)?;
+        write_private_file(&self.path, out.as_bytes())?;
+        debug!("wrote {} entries to state file at {}", self.entries.len(), self.path.display());
+        Ok(())
+    }
+}
+
+/// An inventory entry, along with its current status from the API
+#[derive(Serialize, Debug)]
+pub struct ResourceStatus {
+    #[serde(flatten)]
+    pub entry: InventoryEntry,
+    pub status: String
+}
+
+impl ResultFormatting for ResourceStatus {
+    fn compact(&self) -> String {
+        format!("{}, {}, {}, {}, {}", self.entry.name, self.entry.id, self.entry.kind, self.entry.created_at.to_rfc3339(), self.status)
+    }
+}
+
+const STATUS_HEADERS: &[&str] = &["ID", "NAME", "KIND", "REGION", "CREATED AT", "EXPIRES AT", "STATUS"];
+
+impl TableFormatting for ResourceStatus {
+    fn headers(&self) -> Vec<String> {
+        headers(STATUS_HEADERS)
+    }
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![self.entry.id.clone(), self.entry.name.clone(), self.entry.kind.to_string(),
+            self.entry.region.clone().unwrap_or_default(), self.entry.created_at.to_rfc3339(),
+            self.entry.expires_at.map(|e| e.to_rfc3339()).unwrap_or_default(), self.status.clone()]]
+    }
+}
+
+/// the status of every resource in the inventory
+#[derive(Serialize, Debug)]
+pub struct ResourceStatusList {
+    pub items: Vec<ResourceStatus>
+}
+
+impl ResultFormatting for ResourceStatusList {
+    fn compact(&self) -> String {
+        let mut acc = String::new();
+        for item in &self.items{
+            acc = format!("{}{}\n", acc, item.compact());
+        };
+        acc
+    }
+}
+
+impl ListFormatting for ResourceStatusList {
+    fn list_rows(&self) -> Result<Vec<serde_json::Value>> {
+        list_rows(&self.items)
+    }
+}
+
+impl TableFormatting for ResourceStatusList {
+    fn headers(&self) -> Vec<String> {
+        headers(STATUS_HEADERS)
+    }
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.items.iter().flat_map(|i| i.rows()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Inventory, InventoryEntry, ResourceKind};
+
+    #[test]
+    fn test_record_remove() {
+        let mut path = std::env::temp_dir();
+        path.push(format!("esscli-test-state-{}", std::process::id()));
+        path.push("state.json");
+        let _ = std::fs::remove_file(&path);
+
+        let mut inv = Inventory::open(path.clone()).unwrap();
+        inv.record(InventoryEntry::new("abc", ResourceKind::Serverless, "first", Some("aws-eu-west-1".to_string()), "https://example.com"));
+        inv.record(InventoryEntry::new("def", ResourceKind::Stateful, "second", None, "https://example.com"));
+        inv.record(InventoryEntry::new("abc", ResourceKind::Serverless, "renamed", None, "https://example.com"));
+        inv.save().unwrap();
+
+        let mut inv = Inventory::open(path.clone()).unwrap();
+        assert_eq!(inv.entries().len(), 2);
+        assert_eq!(inv.entries()[1].name, "renamed");
+        assert_eq!(inv.remove("def").unwrap().kind, ResourceKind::Stateful);
+        assert!(inv.remove("def").is_none());
+    }
+}
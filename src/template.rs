@@ -0,0 +1,199 @@
+use anyhow::{Result, anyhow};
+use chrono::DateTime;
+use serde_json::Value;
+
+use crate::query::{self, Query};
+
+/// A helper applied to a template value, e.g. `{{.name | pad 20}}`
+#[derive(Clone, Debug)]
+enum Helper {
+    /// `date "%Y-%m-%d"`: reformat an RFC 3339 timestamp
+    Date(String),
+    /// `pad N`: left-align to N characters, or right-align if N is negative
+    Pad(i64),
+    /// `trunc N`: cut the value down to at most N characters
+    Trunc(usize),
+    /// `default "-"`: replace empty or missing values
+    Default(String),
+    Upper,
+    Lower
+}
+
+#[derive(Clone, Debug)]
+enum Part {
+    Literal(String),
+    Expr{query: Query, helpers: Vec<Helper>}
+}
+
+/// A user-supplied output template such as `{{.id}}\t{{.name}}\t{{.endpoints.kibana}}`,
+/// rendered once for each item of a result.
+#[derive(Clone, Debug)]
+pub struct Template {
+    parts: Vec<Part>
+}
+
+/// parse a template string. `\t` and `\n` in the literal text are expanded.
+pub fn parse(raw: &str) -> Result<Template> {
+    let mut parts = Vec::new();
+    let mut rest = raw;
+    while let Some(start) = rest.find("{{") {
+        if start > 0 {
+            parts.push(Part::Literal(unescape(&rest[..start])));
+        }
+        let end = rest[start..].find("}}")
+            .ok_or_else(|| anyhow!("unterminated '{{{{' in format '{}'", raw))? + start;
+        parts.push(parse_expr(rest[start + 2..end].trim()).map_err(|e| anyhow!("invalid format '{}': {}", raw, e))?);
+        rest = &rest[end + 2..];
+    }
+    if !rest.is_empty() {
+        parts.push(Part::Literal(unescape(rest)));
+    }
+    Ok(Template { parts })
+}
+
+impl Template {
+    /// render the template against the serialized form of a single item
+    pub fn render(&self, item: &Value) -> Result<String> {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => out.push_str(text),
+                Part::Expr { query, helpers } => {
+                    let vals: Vec<String> = query.eval(item)?.iter().map(to_text).collect();
+                    let mut val = vals.join(",");
+                    for helper in helpers {
+                        val = helper.apply(val);
+                    }
+                    out.push_str(&val);
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+impl Helper {
+    fn apply(&self, val: String) -> String {
+        match self {
+            Helper::Date(fmt) => match DateTime::parse_from_rfc3339(&val) {
+                Result::Ok(ts) => ts.format(fmt).to_string(),
+                Err(_) => val
+            },
+            Helper::Pad(width) if *width < 0 => format!("{:>1$}", val, width.unsigned_abs() as usize),
+            Helper::Pad(width) => format!("{:<1$}", val, *width as usize),
+            Helper::Trunc(max) => val.chars().take(*max).collect(),
+            Helper::Default(def) if val.is_empty() => def.clone(),
+            Helper::Default(_) => val,
+            Helper::Upper => val.to_uppercase(),
+            Helper::Lower => val.to_lowercase()
+        }
+    }
+}
+
+/// the text form of a value: strings are unquoted, null is empty
+fn to_text(val: &Value) -> String {
+    match val {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string()
+    }
+}
+
+/// parse the inside of a `{{ }}` block: a query path followed by any number of `| helper arg` segments
+fn parse_expr(expr: &str) -> Result<Part> {
+    let mut path = Vec::new();
+    let mut helpers = Vec::new();
+    for segment in split_segments(expr) {
+        let segment = segment.trim();
+        // path segments come first, and always start with a '.'
+        if helpers.is_empty() && segment.starts_with('.') {
+            path.push(segment.to_string());
+            continue;
+        }
+        helpers.push(parse_helper(segment)?);
+    }
+    if path.is_empty() {
+        return Err(anyhow!("'{{{{{}}}}}' should start with a path such as .name", expr))
+    }
+    Ok(Part::Expr { query: query::parse(&path.join(" | "))?, helpers })
+}
+
+fn parse_helper(segment: &str) -> Result<Helper> {
+    let (name, arg) = match segment.split_once(char::is_whitespace) {
+        Some((name, arg)) => (name, Some(unquote(arg.trim()))),
+        None => (segment, None)
+    };
+    let int_arg = || -> Result<i64> {
+        arg.as_ref().and_then(|a| a.parse().ok()).ok_or_else(|| anyhow!("{} takes a number, e.g. '{} 20'", name, name))
+    };
+    let helper = match name {
+        "date" => Helper::Date(arg.unwrap_or_else(|| "%Y-%m-%d %H:%M:%S".to_string())),
+        "pad" => Helper::Pad(int_arg()?),
+        "trunc" => Helper::Trunc(int_arg()?.max(0) as usize),
+        "default" => Helper::Default(arg.unwrap_or_default()),
+        "upper" => Helper::Upper,
+        "lower" => Helper::Lower,
+        _ => return Err(anyhow!("unknown helper '{}', expected one of date, pad, trunc, default, upper, lower", name))
+    };
+    Ok(helper)
+}
+
+/// split on pipes that aren't inside quotes
+fn split_segments(expr: &str) -> Vec<String> {
+    let mut segments = vec![String::new()];
+    let mut in_quotes = false;
+    for c in expr.chars() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '|' if !in_quotes => {
+                segments.push(String::new());
+                continue;
+            },
+            _ => {}
+        }
+        segments.last_mut().unwrap().push(c);
+    }
+    segments
+}
+
+fn unquote(arg: &str) -> String {
+    arg.strip_prefix('"').and_then(|a| a.strip_suffix('"')).unwrap_or(arg).to_string()
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("\\t", "\t").replace("\\n", "\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::parse;
+
+    #[test]
+    fn test_render() {
+        let item = json!({
+            "id": "abc",
+            "name": "my-project",
+            "alias": null,
+            "metadata": {"created_at": "2023-08-21T10:11:12.000Z"},
+            "endpoints": {"kibana": "https://kb"}
+        });
+        let tmpl = parse(r"{{.id}}\t{{.name}}\t{{.endpoints.kibana}}").unwrap();
+        assert_eq!(tmpl.render(&item).unwrap(), "abc\tmy-project\thttps://kb");
+
+        let tmpl = parse(r#"[{{.name | pad 12}}] [{{.id | pad -5}}] {{.alias | default "-"}} {{.metadata.created_at | date "%Y-%m-%d"}}"#).unwrap();
+        assert_eq!(tmpl.render(&item).unwrap(), "[my-project  ] [  abc] - 2023-08-21");
+
+        let tmpl = parse("{{ .name | trunc 2 | upper }}").unwrap();
+        assert_eq!(tmpl.render(&item).unwrap(), "MY");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("{{.id").is_err());
+        assert!(parse("{{ name }}").is_err());
+        assert!(parse("{{.id | bogus}}").is_err());
+        assert!(parse("{{.id | pad wide}}").is_err());
+    }
+}
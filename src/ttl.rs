@@ -0,0 +1,55 @@
+use std::time::Duration;
+
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+
+use crate::clients::{create_deployment::DeploymentCreateRequest, deployments::{DeploymentGetResponse, DeploymentMetadata}};
+
+/// deployment metadata tag that holds the RFC 3339 expiry time of a stateful deployment
+pub const EXPIRES_TAG: &str = "esscli_expires_at";
+
+/// compute the expiry time for a resource created at the given time.
+/// `created_at` is the timestamp reported by the API; if it can't be parsed, the current time is used.
+pub fn expiry_from(created_at: Option<&str>, ttl: Duration) -> Result<DateTime<Utc>> {
+    let start = created_at
+        .and_then(|c| DateTime::parse_from_rfc3339(c).ok())
+        .map(|c| c.with_timezone(&Utc))
+        .unwrap_or_else(Utc::now);
+    chrono::Duration::from_std(ttl).ok().and_then(|t| start.checked_add_signed(t))
+        .ok_or_else(|| anyhow!("ttl of {} is too long", humantime::format_duration(ttl)))
+}
+
+/// add the expiry tag to a deployment create request, replacing any existing one
+pub fn tag_deployment_request(req: &mut DeploymentCreateRequest, expires: DateTime<Utc>) {
+    req.metadata.get_or_insert(DeploymentMetadata { tags: None, hidden: None }).set_tag(EXPIRES_TAG, &expires.to_rfc3339());
+}
+
+/// read the expiry time from a deployment's metadata tags, if it has one
+pub fn deployment_expiry(dep: &DeploymentGetResponse) -> Option<DateTime<Utc>> {
+    let tag = dep.metadata.as_ref()?.tag(EXPIRES_TAG)?;
+    DateTime::parse_from_rfc3339(tag).ok().map(|t| t.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{DateTime, Utc};
+
+    use super::expiry_from;
+
+    #[test]
+    fn test_expiry_from_created_at() {
+        let ttl: Duration = "4h".parse::<humantime::Duration>().unwrap().into();
+        let res = expiry_from(Some("2023-08-21T10:00:00.000Z"), ttl).unwrap();
+        assert_eq!(res, "2023-08-21T14:00:00Z".parse::<DateTime<Utc>>().unwrap());
+
+        let ttl: Duration = "1d".parse::<humantime::Duration>().unwrap().into();
+        let before = Utc::now();
+        let res = expiry_from(Some("not a date"), ttl).unwrap();
+        assert!(res >= before + chrono::Duration::days(1));
+
+        let ttl: Duration = "1000000y".parse::<humantime::Duration>().unwrap().into();
+        assert!(expiry_from(None, ttl).is_err());
+    }
+}
@@ -0,0 +1,97 @@
+use std::{io::{IsTerminal, Write}, thread, time::Duration};
+
+use anyhow::Result;
+use chrono::Local;
+
+pub const GREEN: &str = "\x1b[32m";
+pub const YELLOW: &str = "\x1b[33m";
+pub const RED: &str = "\x1b[31m";
+pub const RESET: &str = "\x1b[0m";
+
+/// A single line in the watch view
+#[derive(Clone, Debug, PartialEq)]
+pub struct WatchItem {
+    pub id: String,
+    /// the text displayed for the item
+    pub label: String,
+    /// the part of the item that's compared between polls, such as a phase or health value
+    pub state: String
+}
+
+/// poll for items every `interval` and redraw them in place, highlighting what changed since the last poll.
+/// Runs until interrupted. Errors from a poll are displayed and polling continues.
+pub fn watch<F>(interval: Duration, mut poll: F) -> Result<()>
+where F: FnMut() -> Result<Vec<WatchItem>>
+{
+    let color = std::io::stdout().is_terminal();
+    let title = std::env::args().collect::<Vec<String>>().join(" ");
+    let mut prev: Option<Vec<WatchItem>> = None;
+    loop {
+        let frame = match poll() {
+            Result::Ok(items) => {
+                let frame = render_frame(prev.as_deref(), &items, color);
+                prev = Some(items);
+                frame
+            },
+            Err(e) => format!("error: {:#}\n", e)
+        };
+        // move to the top left and clear the screen before redrawing
+        print!("\x1b[H\x1b[2J");
+        print!("Every {}: {}    {}\n\n{}", humantime::format_duration(interval), title, Local::now().format("%Y-%m-%d %H:%M:%S"), frame);
+        std::io::stdout().flush()?;
+        thread::sleep(interval);
+    }
+}
+
+/// render the current items, marking ones that appeared (+), changed state (~) or disappeared (-) since the previous poll
+pub fn render_frame(prev: Option<&[WatchItem]>, current: &[WatchItem], color: bool) -> String {
+    let paint = |code: &str, line: String| if color { format!("{}{}{}", code, line, RESET) } else { line };
+    let mut out = String::new();
+    for item in current {
+        let before = prev.map(|p| p.iter().find(|i| i.id == item.id));
+        let line = match before {
+            // first poll, nothing to compare against
+            None => format!("  {}", describe(item)),
+            Some(None) => paint(GREEN, format!("+ {}", describe(item))),
+            Some(Some(old)) if old.state != item.state => paint(YELLOW, format!("~ {}: {} -> {}", item.label, old.state, item.state)),
+            Some(Some(_)) => format!("  {}", describe(item))
+        };
+        out.push_str(&line);
+        out.push('\n');
+    }
+    for gone in prev.unwrap_or_default().iter().filter(|p| !current.iter().any(|c| c.id == p.id)) {
+        out.push_str(&paint(RED, format!("- {}", describe(gone))));
+        out.push('\n');
+    }
+    out
+}
+
+fn describe(item: &WatchItem) -> String {
+    if item.state.is_empty() {
+        item.label.clone()
+    } else {
+        format!("{}: {}", item.label, item.state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{WatchItem, render_frame};
+
+    fn item(id: &str, state: &str) -> WatchItem {
+        WatchItem { id: id.to_string(), label: format!("project-{}", id), state: state.to_string() }
+    }
+
+    #[test]
+    fn test_render_frame() {
+        let first = vec![item("a", "initializing"), item("b", "initialized")];
+        assert_eq!(render_frame(None, &first, false), "  project-a: initializing\n  project-b: initialized\n");
+
+        let second = vec![item("a", "initialized"), item("c", "initializing")];
+        assert_eq!(render_frame(Some(&first), &second, false),
+            "~ project-a: initializing -> initialized\n+ project-c: initializing\n- project-b: initialized\n");
+
+        let colored = render_frame(Some(&first), &second, true);
+        assert!(colored.contains("\x1b[32m+ project-c"));
+    }
+}